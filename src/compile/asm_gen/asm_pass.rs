@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use super::division::divide_by_constant;
 use super::tacky;
 use super::{
     BinaryOperator, CondCode, FunctionDefinition, Instruction, Operand, Program, Register,
@@ -12,12 +15,57 @@ pub(super) fn parse_program(program: tacky::Program) -> Program {
 fn parse_function(function: tacky::FunctionDefinition) -> FunctionDefinition {
     match function {
         tacky::FunctionDefinition::Function { identifier, body } => {
+            let uses = count_uses(&body);
             let mut instructions = Vec::new();
-            body.into_iter()
-                .for_each(|instruction| parse_instruction(instruction, &mut instructions));
+            let mut body = body.into_iter().peekable();
+            while let Some(instruction) = body.next() {
+                //a comparison only read by the conditional jump right after it can leave its
+                //result in the flags for the jump, instead of going through a 0 or 1
+                if let tacky::Instruction::BinaryOperator {
+                    binary_operator,
+                    src1,
+                    src2,
+                    dst: tacky::Value::Var(dst),
+                } = &instruction
+                    && is_relational(binary_operator)
+                    && uses.get(dst) == Some(&1)
+                    && let Some(
+                        tacky::Instruction::JumpIfZero {
+                            condition: tacky::Value::Var(condition),
+                            ..
+                        }
+                        | tacky::Instruction::JumpIfNotZero {
+                            condition: tacky::Value::Var(condition),
+                            ..
+                        },
+                    ) = body.peek()
+                    && condition == dst
+                {
+                    let (binary_operator, target) = match body.next().expect("just peeked") {
+                        tacky::Instruction::JumpIfZero { target, .. } => {
+                            (negate_relational(binary_operator), target)
+                        }
+                        tacky::Instruction::JumpIfNotZero { target, .. } => {
+                            (binary_operator.clone(), target)
+                        }
+                        _ => unreachable!("just matched a conditional jump"),
+                    };
+                    instructions.push(Instruction::Cmp {
+                        left_operand: parse_operand(src2.clone()),
+                        right_operand: parse_operand(src1.clone()),
+                    });
+                    instructions.push(Instruction::JmpCC {
+                        cond_code: parse_relational_to_cc(binary_operator),
+                        identifier: target,
+                    });
+                    continue;
+                }
+                parse_instruction(instruction, &mut instructions);
+            }
             FunctionDefinition::Function {
                 name: identifier,
-                instructions: instructions,
+                instructions,
+                frame_pointer: true,
             }
         }
     }
@@ -93,6 +141,19 @@ fn parse_instruction(instruction: tacky::Instruction, instructions: &mut Vec<Ins
                     });
                 }
                 tacky::BinaryOperator::Divide | tacky::BinaryOperator::Remainder => {
+                    if let tacky::Value::Constant(divisor) = src2
+                        && divisor != 0
+                        && divisor != i32::MIN
+                    {
+                        divide_by_constant(
+                            parse_operand(src1),
+                            divisor,
+                            matches!(binary_operator, tacky::BinaryOperator::Remainder),
+                            dst,
+                            instructions,
+                        );
+                        return;
+                    }
                     instructions.push(Instruction::Mov {
                         src: parse_operand(src1),
                         dst: Operand::Register(Register::AX),
@@ -105,7 +166,7 @@ fn parse_instruction(instruction: tacky::Instruction, instructions: &mut Vec<Ins
                             tacky::BinaryOperator::Remainder => Operand::Register(Register::DX),
                             _ => unreachable!("already checked to be divide or remainder"),
                         },
-                        dst: dst,
+                        dst,
                     });
                 }
 
@@ -177,6 +238,27 @@ fn parse_instruction(instruction: tacky::Instruction, instructions: &mut Vec<Ins
                 identifier: target,
             });
         }
+        tacky::Instruction::JumpTable {
+            index,
+            targets,
+            default,
+        } => {
+            let index = parse_operand(index);
+            //compared unsigned, so a negative index is out of range too
+            instructions.push(Instruction::Cmp {
+                left_operand: Operand::Imm(targets.len() as i32),
+                right_operand: index.clone(),
+            });
+            instructions.push(Instruction::JmpCC {
+                cond_code: CondCode::AE,
+                identifier: default.clone(),
+            });
+            instructions.push(Instruction::JumpTable {
+                index,
+                table: format!("{}_table", default),
+                targets,
+            });
+        }
         tacky::Instruction::Label(s) => instructions.push(Instruction::Label(s)),
     }
 }
@@ -221,6 +303,44 @@ fn parse_binary(binary_operator: tacky::BinaryOperator) -> BinaryOperator {
     }
 }
 
+/// how many times each variable is read
+fn count_uses(body: &[tacky::Instruction]) -> HashMap<String, usize> {
+    let mut uses = HashMap::new();
+    for instruction in body {
+        for source in instruction.sources() {
+            if let tacky::Value::Var(name) = source {
+                *uses.entry(name.clone()).or_default() += 1;
+            }
+        }
+    }
+    uses
+}
+
+fn is_relational(binary_operator: &tacky::BinaryOperator) -> bool {
+    matches!(
+        binary_operator,
+        tacky::BinaryOperator::Equal
+            | tacky::BinaryOperator::NotEqual
+            | tacky::BinaryOperator::LessThan
+            | tacky::BinaryOperator::Leq
+            | tacky::BinaryOperator::GreaterThan
+            | tacky::BinaryOperator::Geq
+    )
+}
+
+/// the comparison that's true exactly when this one is false
+fn negate_relational(binary_operator: &tacky::BinaryOperator) -> tacky::BinaryOperator {
+    match binary_operator {
+        tacky::BinaryOperator::Equal => tacky::BinaryOperator::NotEqual,
+        tacky::BinaryOperator::NotEqual => tacky::BinaryOperator::Equal,
+        tacky::BinaryOperator::LessThan => tacky::BinaryOperator::Geq,
+        tacky::BinaryOperator::Leq => tacky::BinaryOperator::GreaterThan,
+        tacky::BinaryOperator::GreaterThan => tacky::BinaryOperator::Leq,
+        tacky::BinaryOperator::Geq => tacky::BinaryOperator::LessThan,
+        _ => unreachable!("only called on relational operators"),
+    }
+}
+
 fn parse_relational_to_cc(binary_operator: tacky::BinaryOperator) -> CondCode {
     match binary_operator {
         tacky::BinaryOperator::Equal => CondCode::E,
@@ -0,0 +1,180 @@
+//! pseudo assembly listing of the asm ir. looks like the emitted at&t but keeps the things code
+//! emission lowers away: pseudo registers print as `%name`, and `AllocateStack` and
+//! `DeallocateStack` print as themselves
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{
+    BinaryOperator, CondCode, FunctionDefinition, Instruction, Operand, Program, Register,
+    UnaryOperator,
+};
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Program::Program(function) = self;
+        write!(f, "{}", function)
+    }
+}
+
+impl Display for FunctionDefinition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let FunctionDefinition::Function {
+            name, instructions, ..
+        } = self;
+        writeln!(f, "{}:", name)?;
+        instructions
+            .iter()
+            .try_for_each(|instruction| writeln!(f, "{}", instruction))
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Mov { src, dst } => write!(f, "    movl {}, {}", src, dst),
+            Instruction::Ret => write!(f, "    ret"),
+            Instruction::AllocateStack(bytes) => write!(f, "    allocate_stack {}", bytes),
+            Instruction::DeallocateStack(bytes) => write!(f, "    deallocate_stack {}", bytes),
+            Instruction::Unary {
+                unary_operator,
+                operand,
+            } => write!(f, "    {} {}", unary_operator, operand),
+            Instruction::Binary {
+                binary_operator,
+                left_operand,
+                right_operand,
+            } => write!(
+                f,
+                "    {} {}, {}",
+                binary_operator, left_operand, right_operand
+            ),
+            Instruction::Idiv(operand) => write!(f, "    idivl {}", operand),
+            Instruction::Imul(operand) => write!(f, "    imull {}", operand),
+            Instruction::Cdq => write!(f, "    cdq"),
+            Instruction::Cmp {
+                left_operand,
+                right_operand,
+            } => write!(f, "    cmpl {}, {}", left_operand, right_operand),
+            Instruction::Test {
+                left_operand,
+                right_operand,
+            } => write!(f, "    testl {}, {}", left_operand, right_operand),
+            Instruction::Jmp(label) => write!(f, "    jmp {}", label),
+            Instruction::JmpCC {
+                cond_code,
+                identifier,
+            } => write!(f, "    j{} {}", cond_code, identifier),
+            Instruction::SetCC { cond_code, operand } => {
+                write!(f, "    set{} {}", cond_code, operand)
+            }
+            Instruction::Label(label) => write!(f, "{}:", label),
+            Instruction::JumpTable {
+                index,
+                table,
+                targets,
+            } => write!(
+                f,
+                "    jump_table {}, {} [{}]",
+                index,
+                table,
+                targets.join(", ")
+            ),
+            Instruction::Push(register) => write!(f, "    pushq {}", register.quad_name()),
+            Instruction::Pop(register) => write!(f, "    popq {}", register.quad_name()),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Imm(val) => write!(f, "${}", val),
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Psuedo(name) => write!(f, "%{}", name),
+            Operand::Stack(offset) => write!(f, "{}(%rbp)", offset),
+            Operand::StackPointer(offset) => write!(f, "{}(%rsp)", offset),
+        }
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Register::AX => "%eax",
+            Register::R10 => "%r10d",
+            Register::DX => "%edx",
+            Register::R11 => "%r11d",
+            Register::CX => "%ecx",
+            Register::BX => "%ebx",
+            Register::SI => "%esi",
+            Register::DI => "%edi",
+            Register::R8 => "%r8d",
+            Register::R9 => "%r9d",
+            Register::R12 => "%r12d",
+            Register::R13 => "%r13d",
+            Register::R14 => "%r14d",
+            Register::R15 => "%r15d",
+        })
+    }
+}
+
+impl Register {
+    /// the full 64 bit register, for pushes and pops
+    fn quad_name(&self) -> &'static str {
+        match self {
+            Register::AX => "%rax",
+            Register::R10 => "%r10",
+            Register::DX => "%rdx",
+            Register::R11 => "%r11",
+            Register::CX => "%rcx",
+            Register::BX => "%rbx",
+            Register::SI => "%rsi",
+            Register::DI => "%rdi",
+            Register::R8 => "%r8",
+            Register::R9 => "%r9",
+            Register::R12 => "%r12",
+            Register::R13 => "%r13",
+            Register::R14 => "%r14",
+            Register::R15 => "%r15",
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Neg => "negl",
+            UnaryOperator::Not => "notl",
+        })
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Add => "addl",
+            BinaryOperator::Sub => "subl",
+            BinaryOperator::Mult => "imull",
+            BinaryOperator::BitwiseAnd => "andl",
+            BinaryOperator::BitwiseOr => "orl",
+            BinaryOperator::BitwiseXor => "xorl",
+            BinaryOperator::LeftShift => "shll",
+            BinaryOperator::RightShift => "sarl",
+            BinaryOperator::LogicalRightShift => "shrl",
+        })
+    }
+}
+
+impl Display for CondCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CondCode::E => "e",
+            CondCode::NE => "ne",
+            CondCode::G => "g",
+            CondCode::GE => "ge",
+            CondCode::L => "l",
+            CondCode::LE => "le",
+            CondCode::AE => "ae",
+        })
+    }
+}
@@ -0,0 +1,232 @@
+//! division and remainder by a constant without idiv
+//!
+//! a power of two divisor is a shift. an arithmetic shift rounds toward negative infinity where c
+//! rounds toward zero, so a negative dividend first gets `2^k - 1` added to it (the bias, built
+//! from its sign bit). any other divisor is a multiply by a "magic" fixed point approximation of
+//! `1/d` (hacker's delight, chapter 10): the high half of the product, shifted, is the quotient
+//! rounded toward negative infinity, and adding its sign bit back rounds it toward zero instead.
+//! remainders are `n - q * d` either way
+//!
+//! `INT_MIN` as a divisor still uses idiv, since its absolute value doesn't fit in an int. there
+//! are no unsigned types yet, whose version would use `mul` and `shrl` and need no sign fixups
+
+use super::{BinaryOperator, Instruction, Operand, Register, UnaryOperator};
+
+/// `dividend / divisor`, or `dividend % divisor`, into `dst`. the divisor can't be 0 or `INT_MIN`
+pub(super) fn divide_by_constant(
+    dividend: Operand,
+    divisor: i32,
+    remainder: bool,
+    dst: Operand,
+    instructions: &mut Vec<Instruction>,
+) {
+    let ax = Operand::Register(Register::AX);
+    let dx = Operand::Register(Register::DX);
+    let binary = |binary_operator, left_operand, right_operand: &Operand| Instruction::Binary {
+        binary_operator,
+        left_operand,
+        right_operand: right_operand.clone(),
+    };
+    let mov = |src: &Operand, dst: &Operand| Instruction::Mov {
+        src: src.clone(),
+        dst: dst.clone(),
+    };
+
+    let magnitude = divisor.unsigned_abs();
+    if magnitude == 1 {
+        //n % 1 and n % -1 are always 0, and n / -1 is just -n
+        if remainder {
+            instructions.push(mov(&Operand::Imm(0), &dst));
+            return;
+        }
+        instructions.push(mov(&dividend, &dst));
+        if divisor < 0 {
+            instructions.push(Instruction::Unary {
+                unary_operator: UnaryOperator::Neg,
+                operand: dst,
+            });
+        }
+        return;
+    }
+
+    if magnitude.is_power_of_two() {
+        let shift = magnitude.trailing_zeros() as i32;
+        instructions.extend([
+            mov(&dividend, &ax),
+            binary(BinaryOperator::RightShift, Operand::Imm(31), &ax),
+            binary(
+                BinaryOperator::LogicalRightShift,
+                Operand::Imm(32 - shift),
+                &ax,
+            ),
+            binary(BinaryOperator::Add, dividend.clone(), &ax),
+        ]);
+        if remainder {
+            //the biased dividend with the low bits cleared is the quotient times the divisor
+            instructions.extend([
+                binary(BinaryOperator::BitwiseAnd, Operand::Imm(-(1 << shift)), &ax),
+                mov(&dividend, &dx),
+                binary(BinaryOperator::Sub, ax, &dx),
+                mov(&dx, &dst),
+            ]);
+            return;
+        }
+        instructions.push(binary(BinaryOperator::RightShift, Operand::Imm(shift), &ax));
+        if divisor < 0 {
+            instructions.push(Instruction::Unary {
+                unary_operator: UnaryOperator::Neg,
+                operand: ax.clone(),
+            });
+        }
+        instructions.push(mov(&ax, &dst));
+        return;
+    }
+
+    let (multiplier, shift) = magic(divisor);
+    instructions.push(mov(&Operand::Imm(multiplier), &ax));
+    instructions.push(Instruction::Imul(dividend.clone()));
+    //the multiplier wrapped around to the other sign, so the product is off by one dividend
+    if divisor > 0 && multiplier < 0 {
+        instructions.push(binary(BinaryOperator::Add, dividend.clone(), &dx));
+    } else if divisor < 0 && multiplier > 0 {
+        instructions.push(binary(BinaryOperator::Sub, dividend.clone(), &dx));
+    }
+    if shift > 0 {
+        instructions.push(binary(BinaryOperator::RightShift, Operand::Imm(shift), &dx));
+    }
+    instructions.extend([
+        mov(&dx, &ax),
+        binary(BinaryOperator::LogicalRightShift, Operand::Imm(31), &ax),
+        binary(BinaryOperator::Add, ax.clone(), &dx),
+    ]);
+    if remainder {
+        instructions.extend([
+            binary(BinaryOperator::Mult, Operand::Imm(divisor), &dx),
+            mov(&dividend, &ax),
+            binary(BinaryOperator::Sub, dx, &ax),
+            mov(&ax, &dst),
+        ]);
+    } else {
+        instructions.push(mov(&dx, &dst));
+    }
+}
+
+/// the multiplier and shift for a divisor whose absolute value is at least 2 (hacker's delight,
+/// figure 10-1)
+fn magic(divisor: i32) -> (i32, i32) {
+    const TWO_31: u32 = 1 << 31;
+    let magnitude = divisor.unsigned_abs();
+    let t = TWO_31 + ((divisor as u32) >> 31);
+    //the largest dividend magnitude whose remainder is magnitude - 1
+    let anc = t - 1 - t % magnitude;
+    let mut p = 31;
+    let (mut q1, mut r1) = (TWO_31 / anc, TWO_31 % anc);
+    let (mut q2, mut r2) = (TWO_31 / magnitude, TWO_31 % magnitude);
+    loop {
+        p += 1;
+        q1 = q1.wrapping_mul(2);
+        r1 = r1.wrapping_mul(2);
+        if r1 >= anc {
+            q1 = q1.wrapping_add(1);
+            r1 = r1.wrapping_sub(anc);
+        }
+        q2 = q2.wrapping_mul(2);
+        r2 = r2.wrapping_mul(2);
+        if r2 >= magnitude {
+            q2 = q2.wrapping_add(1);
+            r2 = r2.wrapping_sub(magnitude);
+        }
+        let delta = magnitude - r2;
+        if !(q1 < delta || (q1 == delta && r1 == 0)) {
+            break;
+        }
+    }
+    let multiplier = q2.wrapping_add(1) as i32;
+    let multiplier = if divisor < 0 {
+        multiplier.wrapping_neg()
+    } else {
+        multiplier
+    };
+    (multiplier, p - 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::sink;
+
+    use super::magic;
+    use crate::compile::{CompileOptions, compile, run_native};
+
+    #[test]
+    fn magic_matches_hackers_delight() {
+        let published = [
+            (3, 0x55555556, 0),
+            (5, 0x66666667, 1),
+            (7, 0x92492493_u32 as i32, 2),
+            (-5, 0x99999999_u32 as i32, 1),
+            (-7, 0x6DB6DB6D, 2),
+        ];
+        for (divisor, multiplier, shift) in published {
+            assert_eq!(magic(divisor), (multiplier, shift), "{}", divisor);
+        }
+    }
+
+    /// every constant quotient and remainder is checked against idiv, which a divisor in a
+    /// variable still goes through. the exit status says which check failed
+    #[test]
+    fn constant_divisors_agree_with_idiv() {
+        let dividends = [
+            "-2147483647 - 1",
+            "2147483647",
+            "-1",
+            "0",
+            "1",
+            "12345",
+            "-98765",
+        ];
+        let divisors = [
+            1,
+            -1,
+            2,
+            -2,
+            8,
+            -16,
+            1 << 30,
+            -(1 << 30),
+            3,
+            5,
+            7,
+            -5,
+            -7,
+            6,
+            641,
+            i32::MAX,
+            -i32::MAX,
+        ];
+        let mut source = String::from("int main(void) {\n    int n;\n    int d;\n");
+        let mut check = 0;
+        for dividend in dividends {
+            source += &format!("    n = {};\n", dividend);
+            for divisor in divisors {
+                check += 1;
+                //the one quotient that overflows
+                if dividend.starts_with("-2147483647") && divisor == -1 {
+                    continue;
+                }
+                let divisor = match divisor {
+                    ..0 => format!("-{}", divisor.unsigned_abs()),
+                    _ => divisor.to_string(),
+                };
+                source += &format!(
+                    "    d = {0};\n    if (n / {0} != n / d || n % {0} != n % d) return {1};\n",
+                    divisor, check
+                );
+            }
+        }
+        source += "    return 0;\n}\n";
+
+        let mut asm = Vec::new();
+        compile(&mut asm, &mut sink(), source, &CompileOptions::default()).expect("compiles");
+        assert_eq!(run_native(&asm, "division"), 0);
+    }
+}
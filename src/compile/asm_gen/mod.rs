@@ -1,27 +1,41 @@
 mod asm_pass;
+mod display;
+mod division;
+mod peephole_pass;
+mod register_allocation;
 mod replace_pseudoregisters_pass;
 mod resolve_stack_pass;
 
 use super::Result;
+use super::cfg::{CfgInstruction, Flow};
 use super::tacky;
 use asm_pass::parse_program;
+use peephole_pass::peephole;
+use register_allocation::allocate_registers;
 use replace_pseudoregisters_pass::replace_psuedoregisters;
 use resolve_stack_pass::resolve_stack;
+use serde::Serialize;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Program {
     Program(FunctionDefinition),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum FunctionDefinition {
     Function {
         name: String,
         instructions: Vec<Instruction>,
+        /// whether the function sets up `%rbp` as a frame pointer. without one, stack slots are
+        /// addressed off `%rsp` instead
+        frame_pointer: bool,
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Instruction {
     Mov {
         src: Operand,
@@ -29,6 +43,9 @@ pub(super) enum Instruction {
     },
     Ret,
     AllocateStack(i64),
+    /// gives back what `AllocateStack` took, before returning from a function without a frame
+    /// pointer
+    DeallocateStack(i64),
     Unary {
         unary_operator: UnaryOperator,
         operand: Operand,
@@ -39,11 +56,17 @@ pub(super) enum Instruction {
         right_operand: Operand,
     },
     Idiv(Operand),
+    /// the one operand form, which leaves the full 64 bit product of eax and the operand in edx:eax
+    Imul(Operand),
     Cdq,
     Cmp {
         left_operand: Operand,
         right_operand: Operand,
     },
+    Test {
+        left_operand: Operand,
+        right_operand: Operand,
+    },
     Jmp(String),
     JmpCC {
         cond_code: CondCode,
@@ -54,15 +77,26 @@ pub(super) enum Instruction {
         operand: Operand,
     },
     Label(String),
+    /// an indirect jump to `targets[index]` through a table in `.rodata`. the index has to be in
+    /// range already
+    JumpTable {
+        index: Operand,
+        table: String,
+        targets: Vec<String>,
+    },
+    Push(Register),
+    Pop(Register),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum UnaryOperator {
     Neg,
     Not,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum BinaryOperator {
     Add,
     Sub,
@@ -72,26 +106,41 @@ pub(super) enum BinaryOperator {
     BitwiseXor,
     LeftShift,
     RightShift,
+    LogicalRightShift,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Operand {
     Imm(i32),
     Register(Register),
     Psuedo(String),
     Stack(i64),
+    /// a stack slot relative to `%rsp`, once the frame pointer is omitted
+    StackPointer(i64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Register {
     AX,
     R10,
     DX,
     R11,
     CX,
+    BX,
+    SI,
+    DI,
+    R8,
+    R9,
+    R12,
+    R13,
+    R14,
+    R15,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum CondCode {
     E,
     NE,
@@ -99,11 +148,78 @@ pub(super) enum CondCode {
     GE,
     L,
     LE,
+    /// unsigned above or equal
+    AE,
+}
+
+impl Instruction {
+    /// every operand the instruction reads or writes, in the order they're written
+    pub(super) fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Instruction::Mov { src, dst } => vec![src, dst],
+            Instruction::Binary {
+                left_operand,
+                right_operand,
+                ..
+            }
+            | Instruction::Cmp {
+                left_operand,
+                right_operand,
+            }
+            | Instruction::Test {
+                left_operand,
+                right_operand,
+            } => vec![left_operand, right_operand],
+            Instruction::Unary { operand, .. }
+            | Instruction::Idiv(operand)
+            | Instruction::Imul(operand)
+            | Instruction::SetCC { operand, .. }
+            | Instruction::JumpTable { index: operand, .. } => vec![operand],
+            Instruction::Ret
+            | Instruction::AllocateStack(_)
+            | Instruction::DeallocateStack(_)
+            | Instruction::Cdq
+            | Instruction::Jmp(_)
+            | Instruction::JmpCC { .. }
+            | Instruction::Label(_)
+            | Instruction::Push(_)
+            | Instruction::Pop(_) => vec![],
+        }
+    }
+}
+
+impl CfgInstruction for Instruction {
+    fn flow(&self) -> Flow<'_> {
+        match self {
+            Instruction::Label(label) => Flow::Label(label),
+            Instruction::Jmp(target) => Flow::Jump(target),
+            Instruction::JmpCC { identifier, .. } => Flow::ConditionalJump(identifier),
+            Instruction::JumpTable { targets, .. } => {
+                Flow::MultiJump(targets.iter().map(String::as_str).collect())
+            }
+            Instruction::Ret => Flow::Return,
+            Instruction::Mov { .. }
+            | Instruction::AllocateStack(_)
+            | Instruction::DeallocateStack(_)
+            | Instruction::Unary { .. }
+            | Instruction::Binary { .. }
+            | Instruction::Idiv(_)
+            | Instruction::Imul(_)
+            | Instruction::Cdq
+            | Instruction::Cmp { .. }
+            | Instruction::Test { .. }
+            | Instruction::SetCC { .. }
+            | Instruction::Push(_)
+            | Instruction::Pop(_) => Flow::Straight,
+        }
+    }
 }
 
-pub(super) fn asm_gen(ast: tacky::Program) -> Result<Program> {
+pub(super) fn asm_gen(ast: tacky::Program, omit_frame_pointer: bool) -> Result<Program> {
     let mut program = parse_program(ast);
+    allocate_registers(&mut program);
     replace_psuedoregisters(&mut program);
-    resolve_stack(&mut program);
+    resolve_stack(&mut program, omit_frame_pointer);
+    peephole(&mut program);
     Ok(program)
 }
@@ -0,0 +1,295 @@
+//! small pattern based clean ups over the fixed up instructions
+//!
+//! `mov`s that do nothing and jumps to the very next label are deleted, reloads of a stack slot that
+//! a register still holds are forwarded from that register, and some instructions get
+//! cheaper equivalents. `test` sets the flags exactly like `cmp $0` does, but the other equivalents
+//! set them differently, so they're only used when nothing reads the flags before they are next
+//! written
+
+use std::collections::{HashMap, HashSet};
+use std::mem::take;
+
+use super::{
+    BinaryOperator, FunctionDefinition, Instruction, Operand, Program, Register, UnaryOperator,
+};
+
+pub(super) fn peephole(program: &mut Program) {
+    let Program::Program(FunctionDefinition::Function { instructions, .. }) = program;
+    remove_redundant(instructions);
+    forward_reloads(instructions);
+    let labels = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instruction)| match instruction {
+            Instruction::Label(label) => Some((label.clone(), i)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    for i in 0..instructions.len() {
+        if let Some((cheaper, changes_flags)) = cheaper_equivalent(&instructions[i])
+            && !(changes_flags && flags_live_after(instructions, &labels, i))
+        {
+            instructions[i] = cheaper;
+        }
+    }
+}
+
+fn remove_redundant(instructions: &mut Vec<Instruction>) {
+    for instruction in take(instructions) {
+        match (instructions.last(), &instruction) {
+            (_, Instruction::Mov { src, dst }) if src == dst => continue,
+            //the previous move already made both sides equal
+            (
+                Some(Instruction::Mov { src, dst }),
+                Instruction::Mov {
+                    src: back,
+                    dst: forth,
+                },
+            ) if src == forth && dst == back => {
+                continue;
+            }
+            (_, Instruction::Label(label)) => {
+                while let Some(
+                    Instruction::Jmp(target)
+                    | Instruction::JmpCC {
+                        identifier: target, ..
+                    },
+                ) = instructions.last()
+                    && target == label
+                {
+                    instructions.pop();
+                }
+            }
+            _ => (),
+        }
+        instructions.push(instruction);
+    }
+}
+
+/// drops loads and stores between a stack slot and a register already holding the same value, and
+/// turns loads of a slot another register holds into register moves. what each register mirrors is
+/// only tracked within a basic block, and forgotten as soon as either side is written
+fn forward_reloads(instructions: &mut Vec<Instruction>) {
+    let mut mirrors: Vec<(Register, Operand)> = Vec::new();
+    for mut instruction in take(instructions) {
+        match &instruction {
+            Instruction::Mov {
+                src: Operand::Register(register),
+                dst: slot @ (Operand::Stack(_) | Operand::StackPointer(_)),
+            }
+            | Instruction::Mov {
+                src: slot @ (Operand::Stack(_) | Operand::StackPointer(_)),
+                dst: Operand::Register(register),
+            } if mirrors.contains(&(*register, slot.clone())) => continue,
+            Instruction::Mov {
+                src: slot @ (Operand::Stack(_) | Operand::StackPointer(_)),
+                dst: dst @ Operand::Register(_),
+            } => {
+                if let Some((mirror, _)) = mirrors.iter().find(|(_, mirrored)| mirrored == slot) {
+                    instruction = Instruction::Mov {
+                        src: Operand::Register(*mirror),
+                        dst: dst.clone(),
+                    };
+                }
+            }
+            _ => (),
+        }
+        match &instruction {
+            //the block ends, or rsp moves and takes the slots addressed off it along
+            Instruction::Label(_)
+            | Instruction::Jmp(_)
+            | Instruction::JmpCC { .. }
+            | Instruction::JumpTable { .. }
+            | Instruction::Ret
+            | Instruction::AllocateStack(_)
+            | Instruction::DeallocateStack(_)
+            | Instruction::Push(_)
+            | Instruction::Pop(_) => mirrors.clear(),
+            _ => {
+                let written = written(&instruction);
+                mirrors.retain(|(register, slot)| {
+                    !written.contains(&Operand::Register(*register)) && !written.contains(slot)
+                });
+            }
+        }
+        match &instruction {
+            Instruction::Mov {
+                src: Operand::Register(register),
+                dst: slot @ (Operand::Stack(_) | Operand::StackPointer(_)),
+            }
+            | Instruction::Mov {
+                src: slot @ (Operand::Stack(_) | Operand::StackPointer(_)),
+                dst: Operand::Register(register),
+            } => mirrors.push((*register, slot.clone())),
+            //the copy holds whatever the original does
+            Instruction::Mov {
+                src: Operand::Register(src),
+                dst: Operand::Register(dst),
+            } => {
+                let copied = mirrors
+                    .iter()
+                    .filter(|(register, _)| register == src)
+                    .map(|(_, slot)| (*dst, slot.clone()))
+                    .collect::<Vec<_>>();
+                mirrors.extend(copied);
+            }
+            _ => (),
+        }
+        instructions.push(instruction);
+    }
+}
+
+/// the registers and stack slots an instruction writes
+fn written(instruction: &Instruction) -> Vec<Operand> {
+    let ax = Operand::Register(Register::AX);
+    let dx = Operand::Register(Register::DX);
+    match instruction {
+        Instruction::Mov { dst, .. } => vec![dst.clone()],
+        Instruction::Binary { right_operand, .. } => vec![right_operand.clone()],
+        Instruction::Unary { operand, .. } | Instruction::SetCC { operand, .. } => {
+            vec![operand.clone()]
+        }
+        Instruction::Idiv(_) | Instruction::Imul(_) => vec![ax, dx],
+        Instruction::Cdq => vec![dx],
+        Instruction::Pop(register) => vec![Operand::Register(*register)],
+        Instruction::Cmp { .. }
+        | Instruction::Test { .. }
+        | Instruction::Ret
+        | Instruction::AllocateStack(_)
+        | Instruction::DeallocateStack(_)
+        | Instruction::Jmp(_)
+        | Instruction::JmpCC { .. }
+        | Instruction::JumpTable { .. }
+        | Instruction::Label(_)
+        | Instruction::Push(_) => vec![],
+    }
+}
+
+/// the replacement, and whether it leaves the flags different from the original
+fn cheaper_equivalent(instruction: &Instruction) -> Option<(Instruction, bool)> {
+    Some(match instruction {
+        Instruction::Mov {
+            src: Operand::Imm(0),
+            dst: dst @ Operand::Register(_),
+        } => (
+            Instruction::Binary {
+                binary_operator: BinaryOperator::BitwiseXor,
+                left_operand: dst.clone(),
+                right_operand: dst.clone(),
+            },
+            true,
+        ),
+        Instruction::Cmp {
+            left_operand: Operand::Imm(0),
+            right_operand: operand @ Operand::Register(_),
+        } => (
+            Instruction::Test {
+                left_operand: operand.clone(),
+                right_operand: operand.clone(),
+            },
+            false,
+        ),
+        Instruction::Binary {
+            binary_operator: BinaryOperator::Mult,
+            left_operand: Operand::Imm(val),
+            right_operand,
+        } if *val > 1 && (*val as u32).is_power_of_two() => (
+            Instruction::Binary {
+                binary_operator: BinaryOperator::LeftShift,
+                left_operand: Operand::Imm(val.trailing_zeros() as i32),
+                right_operand: right_operand.clone(),
+            },
+            true,
+        ),
+        _ => return None,
+    })
+}
+
+/// whether anything might read the flags set by instruction `i` before they're overwritten.
+/// unconditional jumps are followed, and a conditional jump counts as a read
+fn flags_live_after(
+    instructions: &[Instruction],
+    labels: &HashMap<String, usize>,
+    i: usize,
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut next = i + 1;
+    while let Some(instruction) = instructions.get(next) {
+        if !visited.insert(next) {
+            //went round a loop without reading them
+            return false;
+        }
+        next += 1;
+        match instruction {
+            //a jump table's targets might read them
+            Instruction::JmpCC { .. }
+            | Instruction::SetCC { .. }
+            | Instruction::JumpTable { .. } => return true,
+            Instruction::Jmp(target) => match labels.get(target) {
+                Some(label) => next = *label,
+                None => return true,
+            },
+            Instruction::Ret
+            | Instruction::Cmp { .. }
+            | Instruction::Test { .. }
+            | Instruction::Binary { .. }
+            | Instruction::Idiv(_)
+            | Instruction::Imul(_)
+            | Instruction::Unary {
+                unary_operator: UnaryOperator::Neg,
+                ..
+            } => return false,
+            Instruction::Mov { .. }
+            | Instruction::Unary { .. }
+            | Instruction::Label(_)
+            | Instruction::Cdq
+            | Instruction::AllocateStack(_)
+            | Instruction::DeallocateStack(_)
+            | Instruction::Push(_)
+            | Instruction::Pop(_) => (),
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instruction, Operand, Register, forward_reloads};
+
+    fn mov(src: Operand, dst: Operand) -> Instruction {
+        Instruction::Mov { src, dst }
+    }
+
+    #[test]
+    fn reloads_are_forwarded_until_either_side_is_written() {
+        let (r10, r11) = (
+            Operand::Register(Register::R10),
+            Operand::Register(Register::R11),
+        );
+        let slot = Operand::Stack(-4);
+        let mut instructions = vec![
+            mov(r10.clone(), slot.clone()),
+            mov(slot.clone(), r10.clone()),
+            mov(slot.clone(), r11.clone()),
+            mov(r11.clone(), slot.clone()),
+            mov(Operand::Imm(1), r10.clone()),
+            mov(slot.clone(), r10.clone()),
+            mov(Operand::Imm(2), slot.clone()),
+            mov(slot.clone(), r11.clone()),
+            Instruction::Label("next".to_string()),
+            mov(slot.clone(), r10.clone()),
+        ];
+        forward_reloads(&mut instructions);
+        let expected = vec![
+            mov(r10.clone(), slot.clone()),
+            mov(r10.clone(), r11.clone()),
+            mov(Operand::Imm(1), r10.clone()),
+            mov(r11.clone(), r10.clone()),
+            mov(Operand::Imm(2), slot.clone()),
+            mov(slot.clone(), r11),
+            Instruction::Label("next".to_string()),
+            mov(slot, r10),
+        ];
+        assert_eq!(format!("{:?}", instructions), format!("{:?}", expected));
+    }
+}
@@ -0,0 +1,447 @@
+//! chaitin-briggs graph colouring register allocation
+//!
+//! every pseudo register and every hard register we allocate from gets a node in an interference
+//! graph, with an edge between two nodes when one is written while the other is still live. the
+//! graph is simplified by repeatedly pruning a node with fewer neighbours than there are registers
+//! (or, when there's none, the pseudo that's cheapest to spill), then coloured in the reverse order.
+//! pseudos left without a colour are spilled, and replace_pseudoregisters_pass gives them stack slots
+//! by colouring their own interference graph, with as many colours as it takes. r10 and r11 are
+//! kept out of it since resolve_stack_pass needs them for fix ups
+//!
+//! before colouring, the two sides of a `mov` that don't interfere are merged into one node so the
+//! move disappears. merging is conservative (the briggs test, or the george test when one side is a
+//! hard register) so it never turns a colourable graph into one that needs spills
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use super::{FunctionDefinition, Instruction, Operand, Program, Register};
+use crate::compile::cfg::{Cfg, NodeId};
+
+/// the registers pseudos can be given, caller saved ones first
+const ALLOCATABLE: [Register; 12] = [
+    Register::AX,
+    Register::CX,
+    Register::DX,
+    Register::SI,
+    Register::DI,
+    Register::R8,
+    Register::R9,
+    Register::BX,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+const CALLEE_SAVED: [Register; 5] = [
+    Register::BX,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+#[derive(Debug, Default)]
+struct Node {
+    neighbours: BTreeSet<Operand>,
+    /// how many times the pseudo is used, as a stand in for how many loads and stores spilling it
+    /// would add
+    spill_cost: f64,
+}
+
+type Graph = BTreeMap<Operand, Node>;
+
+pub(super) fn allocate_registers(program: &mut Program) {
+    let Program::Program(FunctionDefinition::Function { instructions, .. }) = program;
+    let mut cfg = Cfg::new(std::mem::take(instructions));
+    let graph = loop {
+        let mut graph = build_interference_graph(&cfg);
+        let coalesced = coalesce(&mut graph, &cfg);
+        if coalesced.is_empty() {
+            break graph;
+        }
+        rewrite_coalesced(&mut cfg, &coalesced);
+    };
+    let assignment = colour_graph(&graph);
+    *instructions = cfg.into_instructions();
+
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            if let Operand::Psuedo(name) = operand
+                && let Some(register) = assignment.get(name)
+            {
+                *operand = Operand::Register(*register);
+            }
+        }
+    }
+    instructions
+        .retain(|instruction| !matches!(instruction, Instruction::Mov { src, dst } if src == dst));
+    save_callee_saved(instructions, &assignment);
+}
+
+/// for each pseudo, the other pseudos that are live while it's written. replace_pseudoregisters_pass
+/// uses it to let spilled pseudos share stack slots
+pub(super) fn pseudo_interference(
+    instructions: &mut Vec<Instruction>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let cfg = Cfg::new(std::mem::take(instructions));
+    let graph = build_interference_graph(&cfg);
+    *instructions = cfg.into_instructions();
+    graph
+        .into_iter()
+        .filter_map(|(operand, node)| match operand {
+            Operand::Psuedo(name) => Some((
+                name,
+                node.neighbours
+                    .into_iter()
+                    .filter_map(|neighbour| match neighbour {
+                        Operand::Psuedo(neighbour) => Some(neighbour),
+                        _ => None,
+                    })
+                    .collect(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// pushes every callee saved register the function uses on the way in and pops them again before
+/// each return
+fn save_callee_saved(instructions: &mut Vec<Instruction>, assignment: &HashMap<String, Register>) {
+    let used = CALLEE_SAVED
+        .into_iter()
+        .filter(|register| assignment.values().any(|assigned| assigned == register))
+        .collect::<Vec<_>>();
+    if used.is_empty() {
+        return;
+    }
+    let old_instructions = std::mem::take(instructions);
+    instructions.extend(used.iter().map(|register| Instruction::Push(*register)));
+    for instruction in old_instructions {
+        if let Instruction::Ret = instruction {
+            instructions.extend(
+                used.iter()
+                    .rev()
+                    .map(|register| Instruction::Pop(*register)),
+            );
+        }
+        instructions.push(instruction);
+    }
+}
+
+/// the registers and pseudos an instruction reads and the ones it writes
+fn uses_and_updates(instruction: &Instruction) -> (Vec<Operand>, Vec<Operand>) {
+    let ax = Operand::Register(Register::AX);
+    let dx = Operand::Register(Register::DX);
+    let (used, updated) = match instruction {
+        Instruction::Mov { src, dst } => (vec![src.clone()], vec![dst.clone()]),
+        Instruction::Binary {
+            left_operand,
+            right_operand,
+            ..
+        } => (
+            vec![left_operand.clone(), right_operand.clone()],
+            vec![right_operand.clone()],
+        ),
+        Instruction::Cmp {
+            left_operand,
+            right_operand,
+        }
+        | Instruction::Test {
+            left_operand,
+            right_operand,
+        } => (vec![left_operand.clone(), right_operand.clone()], vec![]),
+        //setcc only writes the low byte, so whatever was in the rest of the operand is still there
+        Instruction::Unary { operand, .. } | Instruction::SetCC { operand, .. } => {
+            (vec![operand.clone()], vec![operand.clone()])
+        }
+        Instruction::Idiv(operand) => (vec![operand.clone(), ax.clone(), dx.clone()], vec![ax, dx]),
+        Instruction::Imul(operand) => (vec![operand.clone(), ax.clone()], vec![ax, dx]),
+        Instruction::Cdq => (vec![ax], vec![dx]),
+        Instruction::Ret => (vec![ax], vec![]),
+        Instruction::JumpTable { index, .. } => (vec![index.clone()], vec![]),
+        Instruction::Push(register) => (vec![Operand::Register(*register)], vec![]),
+        Instruction::Pop(register) => (vec![], vec![Operand::Register(*register)]),
+        Instruction::AllocateStack(_)
+        | Instruction::DeallocateStack(_)
+        | Instruction::Jmp(_)
+        | Instruction::JmpCC { .. }
+        | Instruction::Label(_) => (vec![], vec![]),
+    };
+    let allocatable = |operand: &Operand| match operand {
+        Operand::Psuedo(_) => true,
+        Operand::Register(register) => ALLOCATABLE.contains(register),
+        Operand::Imm(_) | Operand::Stack(_) | Operand::StackPointer(_) => false,
+    };
+    (
+        used.into_iter().filter(allocatable).collect(),
+        updated.into_iter().filter(allocatable).collect(),
+    )
+}
+
+fn build_interference_graph(cfg: &Cfg<Instruction>) -> Graph {
+    let mut graph = Graph::new();
+    for register in ALLOCATABLE {
+        let node = graph.entry(Operand::Register(register)).or_default();
+        node.spill_cost = f64::INFINITY;
+        node.neighbours = ALLOCATABLE
+            .into_iter()
+            .filter(|other| *other != register)
+            .map(Operand::Register)
+            .collect();
+    }
+    for block in cfg.blocks.values() {
+        for instruction in &block.instructions {
+            let (used, updated) = uses_and_updates(instruction);
+            for operand in used.into_iter().chain(updated) {
+                if let Operand::Psuedo(_) = operand {
+                    graph.entry(operand).or_default().spill_cost += 1.0;
+                }
+            }
+        }
+    }
+
+    let ins = live_registers(cfg);
+    for (id, block) in &cfg.blocks {
+        let mut live = block_out(cfg, &ins, NodeId::Block(*id));
+        for instruction in block.instructions.iter().rev() {
+            let (used, updated) = uses_and_updates(instruction);
+            for written in &updated {
+                for other in &live {
+                    //a move's destination holds the same value as its source, so they can share
+                    let same_value =
+                        matches!(instruction, Instruction::Mov { src, .. } if src == other);
+                    if other != written && !same_value {
+                        add_edge(&mut graph, written, other);
+                    }
+                }
+            }
+            updated.iter().for_each(|written| {
+                live.remove(written);
+            });
+            live.extend(used);
+        }
+    }
+    graph
+}
+
+fn add_edge(graph: &mut Graph, a: &Operand, b: &Operand) {
+    graph
+        .get_mut(a)
+        .expect("every operand has a node")
+        .neighbours
+        .insert(b.clone());
+    graph
+        .get_mut(b)
+        .expect("every operand has a node")
+        .neighbours
+        .insert(a.clone());
+}
+
+/// which node each merged node was merged into
+type Coalesced = HashMap<Operand, Operand>;
+
+fn find(coalesced: &Coalesced, operand: &Operand) -> Operand {
+    let mut operand = operand;
+    while let Some(parent) = coalesced.get(operand) {
+        operand = parent;
+    }
+    operand.clone()
+}
+
+/// merges the operands of every move that can be merged safely, updating the graph as it goes
+fn coalesce(graph: &mut Graph, cfg: &Cfg<Instruction>) -> Coalesced {
+    let mut coalesced = Coalesced::new();
+    for instruction in cfg.blocks.values().flat_map(|block| &block.instructions) {
+        let Instruction::Mov { src, dst } = instruction else {
+            continue;
+        };
+        let src = find(&coalesced, src);
+        let dst = find(&coalesced, dst);
+        if src == dst
+            || !graph.contains_key(&src)
+            || !graph.contains_key(&dst)
+            || graph[&src].neighbours.contains(&dst)
+            || !conservative_coalesceable(graph, &src, &dst)
+        {
+            continue;
+        }
+        //hard registers have to keep their own node
+        let (keep, merge) = match src {
+            Operand::Register(_) => (src, dst),
+            _ => (dst, src),
+        };
+        merge_nodes(graph, &keep, &merge);
+        coalesced.insert(merge, keep);
+    }
+    coalesced
+}
+
+fn conservative_coalesceable(graph: &Graph, src: &Operand, dst: &Operand) -> bool {
+    briggs_test(graph, src, dst)
+        || match (src, dst) {
+            (Operand::Register(_), _) => george_test(graph, src, dst),
+            (_, Operand::Register(_)) => george_test(graph, dst, src),
+            _ => false,
+        }
+}
+
+/// the merged node can still be pruned if fewer than k of its neighbours have k or more neighbours
+fn briggs_test(graph: &Graph, a: &Operand, b: &Operand) -> bool {
+    let k = ALLOCATABLE.len();
+    let significant = graph[a]
+        .neighbours
+        .union(&graph[b].neighbours)
+        .filter(|neighbour| {
+            let mut degree = graph[*neighbour].neighbours.len();
+            //a neighbour of both loses an edge once they're one node
+            if graph[a].neighbours.contains(*neighbour) && graph[b].neighbours.contains(*neighbour)
+            {
+                degree -= 1;
+            }
+            degree >= k
+        })
+        .count();
+    significant < k
+}
+
+/// merging a pseudo into a hard register is safe if each of the pseudo's neighbours already
+/// interferes with the register or can be pruned anyway
+fn george_test(graph: &Graph, register: &Operand, pseudo: &Operand) -> bool {
+    let k = ALLOCATABLE.len();
+    graph[pseudo].neighbours.iter().all(|neighbour| {
+        graph[register].neighbours.contains(neighbour) || graph[neighbour].neighbours.len() < k
+    })
+}
+
+fn merge_nodes(graph: &mut Graph, keep: &Operand, merge: &Operand) {
+    let merged = graph.remove(merge).expect("every operand has a node");
+    for neighbour in merged.neighbours {
+        graph
+            .get_mut(&neighbour)
+            .expect("every operand has a node")
+            .neighbours
+            .remove(merge);
+        add_edge(graph, keep, &neighbour);
+    }
+    graph
+        .get_mut(keep)
+        .expect("every operand has a node")
+        .spill_cost += merged.spill_cost;
+}
+
+/// renames every merged operand and deletes the moves that became self moves
+fn rewrite_coalesced(cfg: &mut Cfg<Instruction>, coalesced: &Coalesced) {
+    for block in cfg.blocks.values_mut() {
+        for instruction in block.instructions.iter_mut() {
+            for operand in instruction.operands_mut() {
+                *operand = find(coalesced, operand);
+            }
+        }
+        block.instructions.retain(
+            |instruction| !matches!(instruction, Instruction::Mov { src, dst } if src == dst),
+        );
+    }
+}
+
+/// the registers and pseudos live at the start of each block, indexed like `cfg.blocks`
+fn live_registers(cfg: &Cfg<Instruction>) -> HashMap<usize, HashSet<Operand>> {
+    let mut ins = cfg
+        .blocks
+        .keys()
+        .map(|id| (*id, HashSet::new()))
+        .collect::<HashMap<_, _>>();
+
+    let mut worklist = cfg.blocks.keys().rev().copied().collect::<VecDeque<_>>();
+    while let Some(id) = worklist.pop_front() {
+        let mut live = block_out(cfg, &ins, NodeId::Block(id));
+        for instruction in cfg.blocks[&id].instructions.iter().rev() {
+            let (used, updated) = uses_and_updates(instruction);
+            updated.iter().for_each(|written| {
+                live.remove(written);
+            });
+            live.extend(used);
+        }
+        if live != ins[&id] {
+            ins.insert(id, live);
+            for predecessor in cfg.predecessors(NodeId::Block(id)) {
+                if let NodeId::Block(predecessor) = predecessor
+                    && !worklist.contains(predecessor)
+                {
+                    worklist.push_back(*predecessor);
+                }
+            }
+        }
+    }
+    ins
+}
+
+/// the union over a block's successors. `ret` reads eax itself, so nothing is live at the exit
+fn block_out(
+    cfg: &Cfg<Instruction>,
+    ins: &HashMap<usize, HashSet<Operand>>,
+    node: NodeId,
+) -> HashSet<Operand> {
+    cfg.successors(node)
+        .iter()
+        .flat_map(|successor| match successor {
+            NodeId::Block(id) => ins[id].clone(),
+            NodeId::Exit => HashSet::new(),
+            NodeId::Entry => unreachable!("entry has no predecessors"),
+        })
+        .collect()
+}
+
+/// colours the graph and returns the register each coloured pseudo ended up with
+fn colour_graph(graph: &Graph) -> HashMap<String, Register> {
+    let k = ALLOCATABLE.len();
+    let mut remaining = graph.keys().cloned().collect::<BTreeSet<_>>();
+    let mut pruned = Vec::new();
+    while !remaining.is_empty() {
+        let degree = |node: &Operand| graph[node].neighbours.intersection(&remaining).count();
+        let chosen = remaining
+            .iter()
+            .find(|node| degree(node) < k)
+            .or_else(|| {
+                remaining.iter().min_by(|a, b| {
+                    let metric = |node: &Operand| graph[node].spill_cost / degree(node) as f64;
+                    metric(a).total_cmp(&metric(b))
+                })
+            })
+            .expect("remaining isn't empty")
+            .clone();
+        remaining.remove(&chosen);
+        pruned.push(chosen);
+    }
+
+    let mut colours = HashMap::<Operand, usize>::new();
+    while let Some(node) = pruned.pop() {
+        let taken = graph[&node]
+            .neighbours
+            .iter()
+            .filter_map(|neighbour| colours.get(neighbour))
+            .collect::<HashSet<_>>();
+        let mut free = (0..k).filter(|colour| !taken.contains(colour));
+        //callee saved registers take the high colours so pseudos only end up in them, and cost a
+        //push and pop, once the caller saved ones are used up
+        let colour = match &node {
+            Operand::Register(register) if CALLEE_SAVED.contains(register) => free.next_back(),
+            _ => free.next(),
+        };
+        if let Some(colour) = colour {
+            colours.insert(node, colour);
+        }
+    }
+
+    let registers = ALLOCATABLE
+        .into_iter()
+        .map(|register| (colours[&Operand::Register(register)], register))
+        .collect::<HashMap<_, _>>();
+    colours
+        .into_iter()
+        .filter_map(|(node, colour)| match node {
+            Operand::Psuedo(name) => Some((name, registers[&colour])),
+            _ => None,
+        })
+        .collect()
+}
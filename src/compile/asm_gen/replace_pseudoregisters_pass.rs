@@ -1,37 +1,77 @@
-use std::{
-    collections::HashMap,
-    sync::atomic::{AtomicI64, Ordering},
-};
+//! gives every pseudo left over from register allocation a stack slot
+//!
+//! pseudos that are never live at the same time share a slot, picked greedily in the order they
+//! first appear: each one takes the lowest slot none of its already placed neighbours in the
+//! interference graph has. every value is an int so far, so every slot is 4 bytes and 4 byte
+//! aligned. resolve_stack_pass rounds the whole frame up to 16 bytes
 
+use std::{cell::Cell, collections::HashMap};
+
+use super::register_allocation::pseudo_interference;
 use super::{FunctionDefinition, Instruction, Operand, Program};
 
 type IdMap = HashMap<String, i64>;
 
-pub(super) static STACK_COUNTER: AtomicI64 = AtomicI64::new(-4);
+const SLOT_SIZE: i64 = 4;
+
+thread_local! {
+    pub(super) static STACK_COUNTER: Cell<i64> = const { Cell::new(-4) };
+}
 
 pub(super) fn replace_psuedoregisters(ast: &mut Program) {
-    let mut map: IdMap = Default::default();
-    parse_program(ast, &mut map)
+    STACK_COUNTER.set(-SLOT_SIZE);
+    parse_program(ast)
 }
 
-fn parse_program(ast: &mut Program, map: &mut IdMap) {
+fn parse_program(ast: &mut Program) {
     match ast {
-        Program::Program(function_definition) => parse_function(function_definition, map),
+        Program::Program(function_definition) => parse_function(function_definition),
     }
 }
 
-fn parse_function(function: &mut FunctionDefinition, map: &mut IdMap) {
+fn parse_function(function: &mut FunctionDefinition) {
     match function {
-        FunctionDefinition::Function {
-            name: _,
-            instructions,
-        } => instructions
-            .iter_mut()
-            .for_each(|instruction| parse_instruction(instruction, map)),
+        FunctionDefinition::Function { instructions, .. } => {
+            let map = assign_slots(instructions);
+            instructions
+                .iter_mut()
+                .for_each(|instruction| parse_instruction(instruction, &map));
+            //pseudos copied into each other can end up in the same slot
+            instructions.retain(
+                |instruction| !matches!(instruction, Instruction::Mov { src, dst } if src == dst),
+            );
+        }
     };
 }
 
-fn parse_instruction(instruction: &mut Instruction, map: &mut IdMap) {
+/// the offset from rbp of each pseudo's slot. leaves `STACK_COUNTER` one slot past the last one
+fn assign_slots(instructions: &mut Vec<Instruction>) -> IdMap {
+    let interference = pseudo_interference(instructions);
+    let mut map = IdMap::new();
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            let Operand::Psuedo(id) = operand else {
+                continue;
+            };
+            if map.contains_key(id) {
+                continue;
+            }
+            let taken = interference[id]
+                .iter()
+                .filter_map(|neighbour| map.get(neighbour))
+                .collect::<Vec<_>>();
+            let offset = (1..)
+                .map(|slot| -SLOT_SIZE * slot)
+                .find(|offset| !taken.contains(&offset))
+                .expect("there's always a free slot");
+            map.insert(id.clone(), offset);
+            STACK_COUNTER.set(STACK_COUNTER.get().min(offset - SLOT_SIZE));
+        }
+    }
+    map
+}
+
+fn parse_instruction(instruction: &mut Instruction, map: &IdMap) {
     match instruction {
         Instruction::Mov { src, dst } => {
             parse_operand(src, map);
@@ -49,37 +89,38 @@ fn parse_instruction(instruction: &mut Instruction, map: &mut IdMap) {
             parse_operand(left_operand, map);
             parse_operand(right_operand, map);
         }
-        Instruction::Idiv(operand) => {
+        Instruction::Idiv(operand) | Instruction::Imul(operand) => {
             parse_operand(operand, map);
         }
         Instruction::Cmp {
             left_operand,
             right_operand,
+        }
+        | Instruction::Test {
+            left_operand,
+            right_operand,
         } => {
             parse_operand(left_operand, map);
             parse_operand(right_operand, map);
         }
 
-        Instruction::SetCC { operand, .. } => parse_operand(operand, map),
+        Instruction::SetCC { operand, .. } | Instruction::JumpTable { index: operand, .. } => {
+            parse_operand(operand, map)
+        }
         Instruction::Jmp(_)
         | Instruction::JmpCC { .. }
         | Instruction::Label(_)
+        | Instruction::Push(_)
+        | Instruction::Pop(_)
         | Instruction::Cdq
         | Instruction::Ret
-        | Instruction::AllocateStack(_) => (),
+        | Instruction::AllocateStack(_)
+        | Instruction::DeallocateStack(_) => (),
     }
 }
 
-fn parse_operand(operand: &mut Operand, map: &mut IdMap) {
+fn parse_operand(operand: &mut Operand, map: &IdMap) {
     if let Operand::Psuedo(id) = operand {
-        *operand = if map.contains_key(id) {
-            Operand::Stack(map[id])
-        } else {
-            let offset = STACK_COUNTER.load(Ordering::Relaxed);
-            let op = Operand::Stack(offset);
-            STACK_COUNTER.fetch_sub(4, Ordering::Relaxed);
-            map.insert(id.to_string(), offset);
-            op
-        }
+        *operand = Operand::Stack(map[id]);
     }
 }
@@ -1,31 +1,86 @@
 use std::mem::take;
-use std::sync::atomic::Ordering;
 
 use super::replace_pseudoregisters_pass::STACK_COUNTER;
 
 use super::{BinaryOperator, FunctionDefinition, Instruction, Operand, Program, Register};
 
-pub(super) fn resolve_stack(ast: &mut Program) {
-    parse_program(ast)
+pub(super) fn resolve_stack(ast: &mut Program, omit_frame_pointer: bool) {
+    parse_program(ast, omit_frame_pointer)
 }
 
-fn parse_program(program: &mut Program) {
+fn parse_program(program: &mut Program, omit_frame_pointer: bool) {
     match program {
-        Program::Program(function_definition) => parse_function(function_definition),
+        Program::Program(function_definition) => {
+            parse_function(function_definition, omit_frame_pointer)
+        }
     }
 }
 
-fn parse_function(function: &mut FunctionDefinition) {
+fn parse_function(function: &mut FunctionDefinition, omit_frame_pointer: bool) {
     match function {
-        FunctionDefinition::Function { instructions, .. } => {
+        FunctionDefinition::Function {
+            instructions,
+            frame_pointer,
+            ..
+        } => {
             let old_instructions = take(instructions);
-            instructions.push(Instruction::AllocateStack(
-                STACK_COUNTER.load(Ordering::Relaxed) * -1,
-            ));
+            let pushed = 8 * old_instructions
+                .iter()
+                .filter(|instruction| matches!(instruction, Instruction::Push(_)))
+                .count() as i64;
+            //slots go down from -4, so the counter sits one slot past the last one
+            let slots = -STACK_COUNTER.get() - 4;
+            let bytes = if slots == 0 {
+                //nothing spilled and nothing called, so rsp can stay wherever the caller left it
+                0
+            } else if !omit_frame_pointer {
+                //rsp has to stay 16 byte aligned once the callee saved registers are pushed below
+                //the locals
+                (slots + pushed + 15) / 16 * 16 - pushed
+            } else {
+                //without the pushed rbp, the return address is the only thing above the locals
+                (slots + pushed + 8 + 15) / 16 * 16 - pushed - 8
+            };
+            if bytes != 0 {
+                instructions.push(Instruction::AllocateStack(bytes));
+            }
             old_instructions
                 .into_iter()
                 .for_each(|instruction| parse_instruction(instruction, instructions));
+            if omit_frame_pointer {
+                *frame_pointer = false;
+                address_from_stack_pointer(instructions, bytes, pushed);
+            }
+        }
+    }
+}
+
+/// rewrites every rbp relative slot to be relative to rsp, and puts rsp back before each return.
+/// rsp moves with every push and pop, so the offsets follow along. the pops in front of a `Ret`
+/// only undo the prologue's pushes, so whatever comes after it starts again from the prologue's
+/// depth
+fn address_from_stack_pointer(instructions: &mut Vec<Instruction>, bytes: i64, pushed: i64) {
+    let mut depth = 0;
+    for mut instruction in take(instructions) {
+        match &instruction {
+            Instruction::Push(_) => depth += 8,
+            Instruction::Pop(_) => depth -= 8,
+            Instruction::Ret => {
+                if bytes != 0 {
+                    instructions.push(Instruction::DeallocateStack(bytes));
+                }
+                instructions.push(instruction);
+                depth = pushed;
+                continue;
+            }
+            _ => (),
         }
+        for operand in instruction.operands_mut() {
+            if let Operand::Stack(offset) = operand {
+                *operand = Operand::StackPointer(bytes + *offset + depth);
+            }
+        }
+        instructions.push(instruction);
     }
 }
 
@@ -36,12 +91,12 @@ fn parse_instruction(instruction: Instruction, new_instructions: &mut Vec<Instru
             dst: dst @ Operand::Stack(_),
         } => {
             new_instructions.push(Instruction::Mov {
-                src: src,
+                src,
                 dst: Operand::Register(Register::R10),
             });
             new_instructions.push(Instruction::Mov {
                 src: Operand::Register(Register::R10),
-                dst: dst,
+                dst,
             });
         }
         Instruction::Binary {
@@ -59,9 +114,9 @@ fn parse_instruction(instruction: Instruction, new_instructions: &mut Vec<Instru
                 dst: Operand::Register(Register::R10),
             });
             new_instructions.push(Instruction::Binary {
-                binary_operator: binary_operator,
+                binary_operator,
                 left_operand: Operand::Register(Register::R10),
-                right_operand: right_operand,
+                right_operand,
             });
         }
         Instruction::Binary {
@@ -74,8 +129,8 @@ fn parse_instruction(instruction: Instruction, new_instructions: &mut Vec<Instru
                 dst: Operand::Register(Register::R11),
             });
             new_instructions.push(Instruction::Binary {
-                binary_operator: binary_operator,
-                left_operand: left_operand,
+                binary_operator,
+                left_operand,
                 right_operand: Operand::Register(Register::R11),
             });
             new_instructions.push(Instruction::Mov {
@@ -90,6 +145,13 @@ fn parse_instruction(instruction: Instruction, new_instructions: &mut Vec<Instru
             });
             new_instructions.push(Instruction::Idiv(Operand::Register(Register::R10)));
         }
+        Instruction::Imul(operand @ Operand::Imm(_)) => {
+            new_instructions.push(Instruction::Mov {
+                src: operand,
+                dst: Operand::Register(Register::R10),
+            });
+            new_instructions.push(Instruction::Imul(Operand::Register(Register::R10)));
+        }
         Instruction::Cmp {
             left_operand: left_operand @ Operand::Stack(_),
             right_operand: right_operand @ Operand::Stack(_),
@@ -100,7 +162,7 @@ fn parse_instruction(instruction: Instruction, new_instructions: &mut Vec<Instru
             });
             new_instructions.push(Instruction::Cmp {
                 left_operand: Operand::Register(Register::R10),
-                right_operand: right_operand,
+                right_operand,
             });
         }
         Instruction::Cmp {
@@ -112,7 +174,7 @@ fn parse_instruction(instruction: Instruction, new_instructions: &mut Vec<Instru
                 dst: Operand::Register(Register::R11),
             });
             new_instructions.push(Instruction::Cmp {
-                left_operand: left_operand,
+                left_operand,
                 right_operand: Operand::Register(Register::R11),
             });
         }
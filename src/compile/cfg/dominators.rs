@@ -0,0 +1,162 @@
+//! dominators by the cooper, harvey and kennedy iteration over reverse postorder, plus the
+//! dominator tree and dominance frontiers built from them. blocks the entry can't reach have no
+//! dominators and are left out
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::{Cfg, NodeId};
+
+/// a step of `Dominators::walk`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Visit {
+    Enter(NodeId),
+    /// every node `node` dominates has been entered and exited
+    Exit(NodeId),
+}
+
+#[derive(Debug)]
+pub(crate) struct Dominators {
+    idoms: HashMap<NodeId, NodeId>,
+    children: HashMap<NodeId, Vec<NodeId>>,
+    frontiers: HashMap<NodeId, BTreeSet<NodeId>>,
+}
+
+impl Dominators {
+    pub(crate) fn new<I>(cfg: &Cfg<I>) -> Self {
+        let reverse_postorder = reverse_postorder(cfg);
+        let order = reverse_postorder
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (*node, i))
+            .collect::<HashMap<_, _>>();
+
+        let mut idoms = HashMap::from([(NodeId::Entry, NodeId::Entry)]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in reverse_postorder.iter().skip(1) {
+                let mut processed = cfg
+                    .predecessors(*node)
+                    .iter()
+                    .filter(|predecessor| idoms.contains_key(*predecessor));
+                let first = *processed.next().expect("rpo visits a predecessor first");
+                let idom = processed.fold(first, |idom, predecessor| {
+                    intersect(&idoms, &order, idom, *predecessor)
+                });
+                if idoms.insert(*node, idom) != Some(idom) {
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = HashMap::<NodeId, Vec<NodeId>>::new();
+        for node in reverse_postorder.iter().skip(1) {
+            children.entry(idoms[node]).or_default().push(*node);
+        }
+
+        let mut frontiers = HashMap::<NodeId, BTreeSet<NodeId>>::new();
+        for node in &reverse_postorder {
+            let predecessors = cfg
+                .predecessors(*node)
+                .iter()
+                .filter(|predecessor| idoms.contains_key(*predecessor))
+                .collect::<Vec<_>>();
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors {
+                let mut runner = *predecessor;
+                while runner != idoms[node] {
+                    frontiers.entry(runner).or_default().insert(*node);
+                    runner = idoms[&runner];
+                }
+            }
+        }
+
+        Dominators {
+            idoms,
+            children,
+            frontiers,
+        }
+    }
+
+    /// the nodes `node` immediately dominates, in reverse postorder
+    pub(crate) fn children(&self, node: NodeId) -> &[NodeId] {
+        self.children.get(&node).map_or(&[], Vec::as_slice)
+    }
+
+    /// a preorder walk of the dominator tree from the entry, children in reverse postorder, that
+    /// also says when it leaves each node's subtree. it keeps its own stack, since a long run of ifs
+    /// makes the tree as deep as the function is long
+    pub(crate) fn walk(&self) -> impl Iterator<Item = Visit> + '_ {
+        let mut stack = vec![Visit::Enter(NodeId::Entry)];
+        std::iter::from_fn(move || {
+            let visit = stack.pop()?;
+            if let Visit::Enter(node) = visit {
+                stack.push(Visit::Exit(node));
+                //reversed so the children come off the stack in order
+                stack.extend(self.children(node).iter().rev().copied().map(Visit::Enter));
+            }
+            Some(visit)
+        })
+    }
+
+    pub(crate) fn frontier(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.frontiers.get(&node).into_iter().flatten().copied()
+    }
+
+    /// whether every path from the entry to `b` goes through `a`
+    pub(crate) fn dominates(&self, a: NodeId, b: NodeId) -> bool {
+        let mut node = b;
+        loop {
+            if node == a {
+                return true;
+            }
+            match self.idoms.get(&node) {
+                Some(idom) if *idom != node => node = *idom,
+                _ => return false,
+            }
+        }
+    }
+}
+
+fn reverse_postorder<I>(cfg: &Cfg<I>) -> Vec<NodeId> {
+    let mut postorder = Vec::new();
+    let mut visited = BTreeSet::from([NodeId::Entry]);
+    //each entry is a node and how many of its successors have been looked at
+    let mut stack = vec![(NodeId::Entry, 0)];
+    while let Some((node, next)) = stack.last_mut() {
+        let node = *node;
+        match cfg.successors(node).get(*next) {
+            Some(successor) => {
+                *next += 1;
+                if visited.insert(*successor) {
+                    stack.push((*successor, 0));
+                }
+            }
+            None => {
+                postorder.push(node);
+                stack.pop();
+            }
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+fn intersect(
+    idoms: &HashMap<NodeId, NodeId>,
+    order: &HashMap<NodeId, usize>,
+    mut a: NodeId,
+    mut b: NodeId,
+) -> NodeId {
+    while a != b {
+        while order[&a] > order[&b] {
+            a = idoms[&a];
+        }
+        while order[&b] > order[&a] {
+            b = idoms[&b];
+        }
+    }
+    a
+}
@@ -0,0 +1,40 @@
+use std::fmt::{Display, Write};
+
+use super::{Cfg, NodeId};
+
+fn node_name(node: NodeId) -> String {
+    match node {
+        NodeId::Entry => "entry".to_string(),
+        NodeId::Block(id) => format!("block{}", id),
+        NodeId::Exit => "exit".to_string(),
+    }
+}
+
+/// graphviz source for the graph, one box per block listing its instructions
+pub(crate) fn to_dot<I: Display>(name: &str, cfg: &Cfg<I>) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph \"{}\" {{", escape(name)).expect("writing to a string");
+    writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").expect("writing to a string");
+    writeln!(dot, "    entry [shape=oval];\n    exit [shape=oval];").expect("writing to a string");
+    for (id, block) in &cfg.blocks {
+        let label = block
+            .instructions
+            .iter()
+            .map(|instruction| format!("{}\\l", escape(instruction.to_string().trim())))
+            .collect::<String>();
+        writeln!(dot, "    block{} [label=\"{}\"];", id, label).expect("writing to a string");
+    }
+    let edges = std::iter::once(NodeId::Entry)
+        .chain(cfg.blocks.keys().map(|id| NodeId::Block(*id)))
+        .flat_map(|from| cfg.successors(from).iter().map(move |to| (from, *to)));
+    for (from, to) in edges {
+        writeln!(dot, "    {} -> {};", node_name(from), node_name(to))
+            .expect("writing to a string");
+    }
+    writeln!(dot, "}}").expect("writing to a string");
+    dot
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,273 @@
+//! control flow graphs over a function body. generic over the instruction type so the tacky
+//! optimizations and anything working on asm_gen instructions can share it
+
+mod dominators;
+mod dot;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+pub(super) use dominators::{Dominators, Visit};
+pub(super) use dot::to_dot;
+
+/// what an instruction does to control flow, which is all the graph needs to know about it
+pub(super) enum Flow<'a> {
+    Label(&'a str),
+    Jump(&'a str),
+    ConditionalJump(&'a str),
+    /// an indirect jump to one of these labels, like a jump table
+    MultiJump(Vec<&'a str>),
+    Return,
+    Straight,
+}
+
+pub(super) trait CfgInstruction {
+    fn flow(&self) -> Flow<'_>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", content = "value")]
+pub(super) enum NodeId {
+    Entry,
+    Block(usize),
+    Exit,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct BasicBlock<I> {
+    pub(super) instructions: Vec<I>,
+    pub(super) predecessors: Vec<NodeId>,
+    pub(super) successors: Vec<NodeId>,
+}
+
+/// blocks are kept in their original order so flattening the graph reproduces the fall through
+/// between them
+#[derive(Debug, Serialize)]
+pub(super) struct Cfg<I> {
+    pub(super) entry_successors: Vec<NodeId>,
+    pub(super) blocks: BTreeMap<usize, BasicBlock<I>>,
+    pub(super) exit_predecessors: Vec<NodeId>,
+}
+
+impl<I: CfgInstruction> Cfg<I> {
+    pub(super) fn new(instructions: Vec<I>) -> Self {
+        let mut blocks = BTreeMap::new();
+        let mut current = Vec::new();
+        for instruction in instructions {
+            match instruction.flow() {
+                Flow::Label(_) => {
+                    if !current.is_empty() {
+                        push_block(&mut blocks, std::mem::take(&mut current));
+                    }
+                    current.push(instruction);
+                }
+                Flow::Jump(_) | Flow::ConditionalJump(_) | Flow::MultiJump(_) | Flow::Return => {
+                    current.push(instruction);
+                    push_block(&mut blocks, std::mem::take(&mut current));
+                }
+                Flow::Straight => current.push(instruction),
+            }
+        }
+        if !current.is_empty() {
+            push_block(&mut blocks, current);
+        }
+
+        let mut cfg = Cfg {
+            entry_successors: Vec::new(),
+            blocks,
+            exit_predecessors: Vec::new(),
+        };
+        cfg.add_all_edges();
+        cfg
+    }
+
+    fn add_all_edges(&mut self) {
+        let labels = self
+            .blocks
+            .iter()
+            .filter_map(|(id, block)| match block.instructions.first()?.flow() {
+                Flow::Label(label) => Some((label.to_string(), NodeId::Block(*id))),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let ids = self.blocks.keys().copied().collect::<Vec<_>>();
+
+        match ids.first() {
+            Some(first) => self.add_edge(NodeId::Entry, NodeId::Block(*first)),
+            None => self.add_edge(NodeId::Entry, NodeId::Exit),
+        }
+
+        for (i, id) in ids.iter().enumerate() {
+            let next = ids
+                .get(i + 1)
+                .map_or(NodeId::Exit, |next| NodeId::Block(*next));
+            //a jump to a label that doesn't exist gets rejected by the assembler, so it's enough
+            //for the graph to treat it as leaving the function
+            let target = |label: &str| labels.get(label).copied().unwrap_or(NodeId::Exit);
+            let successors = match self.blocks[id].instructions.last().map(|i| i.flow()) {
+                Some(Flow::Return) => vec![NodeId::Exit],
+                Some(Flow::Jump(label)) => vec![target(label)],
+                Some(Flow::ConditionalJump(label)) => vec![target(label), next],
+                Some(Flow::MultiJump(labels)) => labels.into_iter().map(target).collect(),
+                Some(Flow::Label(_) | Flow::Straight) | None => vec![next],
+            };
+            successors
+                .into_iter()
+                .for_each(|successor| self.add_edge(NodeId::Block(*id), successor));
+        }
+    }
+}
+
+impl<I> Cfg<I> {
+    /// flattens the graph back into a function body
+    pub(super) fn into_instructions(self) -> Vec<I> {
+        self.blocks
+            .into_values()
+            .flat_map(|block| block.instructions)
+            .collect()
+    }
+
+    pub(super) fn successors(&self, node: NodeId) -> &[NodeId] {
+        match node {
+            NodeId::Entry => &self.entry_successors,
+            NodeId::Block(id) => &self.blocks[&id].successors,
+            NodeId::Exit => &[],
+        }
+    }
+
+    pub(super) fn predecessors(&self, node: NodeId) -> &[NodeId] {
+        match node {
+            NodeId::Entry => &[],
+            NodeId::Block(id) => &self.blocks[&id].predecessors,
+            NodeId::Exit => &self.exit_predecessors,
+        }
+    }
+
+    /// every node that can be reached from the entry, including the entry and exit if they are
+    pub(super) fn reachable(&self) -> HashSet<NodeId> {
+        let mut seen = HashSet::from([NodeId::Entry]);
+        let mut stack = vec![NodeId::Entry];
+        while let Some(node) = stack.pop() {
+            for successor in self.successors(node) {
+                if seen.insert(*successor) {
+                    stack.push(*successor);
+                }
+            }
+        }
+        seen
+    }
+
+    /// deletes a block along with every edge into or out of it
+    pub(super) fn remove_block(&mut self, id: usize) {
+        let block = self.blocks.remove(&id).expect("block exists");
+        let node = NodeId::Block(id);
+        for predecessor in block.predecessors {
+            match predecessor {
+                NodeId::Entry => self.entry_successors.retain(|n| *n != node),
+                NodeId::Block(p) => {
+                    if let Some(p) = self.blocks.get_mut(&p) {
+                        p.successors.retain(|n| *n != node);
+                    }
+                }
+                NodeId::Exit => unreachable!("exit has no successors"),
+            }
+        }
+        for successor in block.successors {
+            match successor {
+                NodeId::Entry => unreachable!("entry has no predecessors"),
+                NodeId::Block(s) => {
+                    if let Some(s) = self.blocks.get_mut(&s) {
+                        s.predecessors.retain(|n| *n != node);
+                    }
+                }
+                NodeId::Exit => self.exit_predecessors.retain(|n| *n != node),
+            }
+        }
+    }
+
+    pub(super) fn add_edge(&mut self, from: NodeId, to: NodeId) {
+        let successors = match from {
+            NodeId::Entry => &mut self.entry_successors,
+            NodeId::Block(id) => &mut self.blocks.get_mut(&id).expect("block exists").successors,
+            NodeId::Exit => unreachable!("exit has no successors"),
+        };
+        if successors.contains(&to) {
+            return;
+        }
+        successors.push(to);
+        match to {
+            NodeId::Entry => unreachable!("entry has no predecessors"),
+            NodeId::Block(id) => self
+                .blocks
+                .get_mut(&id)
+                .expect("block exists")
+                .predecessors
+                .push(from),
+            NodeId::Exit => self.exit_predecessors.push(from),
+        }
+    }
+
+    /// puts a new block with no edges in front of block `at`, renumbering it and the blocks after it
+    pub(super) fn insert_block(&mut self, at: usize, instructions: Vec<I>) {
+        let shift = |node: &mut NodeId| {
+            if let NodeId::Block(id) = node
+                && *id >= at
+            {
+                *id += 1;
+            }
+        };
+        self.blocks = std::mem::take(&mut self.blocks)
+            .into_iter()
+            .map(|(id, mut block)| {
+                block.predecessors.iter_mut().for_each(shift);
+                block.successors.iter_mut().for_each(shift);
+                (if id >= at { id + 1 } else { id }, block)
+            })
+            .collect();
+        self.entry_successors.iter_mut().for_each(shift);
+        self.exit_predecessors.iter_mut().for_each(shift);
+        self.blocks.insert(
+            at,
+            BasicBlock {
+                instructions,
+                predecessors: Vec::new(),
+                successors: Vec::new(),
+            },
+        );
+    }
+
+    pub(super) fn remove_edge(&mut self, from: NodeId, to: NodeId) {
+        match from {
+            NodeId::Entry => self.entry_successors.retain(|n| *n != to),
+            NodeId::Block(id) => self
+                .blocks
+                .get_mut(&id)
+                .expect("block exists")
+                .successors
+                .retain(|n| *n != to),
+            NodeId::Exit => unreachable!("exit has no successors"),
+        }
+        match to {
+            NodeId::Entry => unreachable!("entry has no predecessors"),
+            NodeId::Block(id) => self
+                .blocks
+                .get_mut(&id)
+                .expect("block exists")
+                .predecessors
+                .retain(|n| *n != from),
+            NodeId::Exit => self.exit_predecessors.retain(|n| *n != from),
+        }
+    }
+}
+
+fn push_block<I>(blocks: &mut BTreeMap<usize, BasicBlock<I>>, instructions: Vec<I>) {
+    blocks.insert(
+        blocks.len(),
+        BasicBlock {
+            instructions,
+            predecessors: Vec::new(),
+            successors: Vec::new(),
+        },
+    );
+}
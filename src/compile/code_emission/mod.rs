@@ -30,16 +30,29 @@ impl CodeEmitter for asm_gen::Program {
 impl CodeEmitter for asm_gen::FunctionDefinition {
     fn emit(&self, writer: &mut impl Write) -> Result<()> {
         match self {
-            asm_gen::FunctionDefinition::Function { name, instructions } => {
+            asm_gen::FunctionDefinition::Function {
+                name,
+                instructions,
+                frame_pointer,
+            } => {
                 writeln!(writer, ".globl {}", name)?;
                 #[cfg(target_os = "linux")]
                 writeln!(writer, "{}:", name)?;
                 #[cfg(target_os = "macos")]
                 writeln!(writer, "_{}:", name)?;
 
+                if !frame_pointer {
+                    //the stack was already put back by `DeallocateStack`
+                    return instructions
+                        .iter()
+                        .try_for_each(|instruction| match instruction {
+                            asm_gen::Instruction::Ret => Ok(writeln!(writer, "  ret")?),
+                            instruction => instruction.emit(writer),
+                        });
+                }
                 writeln!(writer, "  pushq %rbp\n  movq %rsp, %rbp")?;
                 instructions
-                    .into_iter()
+                    .iter()
                     .try_for_each(|instruction| instruction.emit(writer))?
             }
         }
@@ -55,12 +68,15 @@ impl CodeEmitter for asm_gen::Instruction {
                 src.emit(writer)?;
                 write!(writer, ", ")?;
                 dst.emit(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Ret => writeln!(writer, "  movq %rbp, %rsp\n  popq %rbp\n  ret")?,
             asm_gen::Instruction::AllocateStack(offset) => {
                 writeln!(writer, "  subq ${}, %rsp", offset)?
             }
+            asm_gen::Instruction::DeallocateStack(offset) => {
+                writeln!(writer, "  addq ${}, %rsp", offset)?
+            }
             asm_gen::Instruction::Unary {
                 unary_operator,
                 operand,
@@ -68,7 +84,7 @@ impl CodeEmitter for asm_gen::Instruction {
                 unary_operator.emit(writer)?;
                 write!(writer, " ")?;
                 operand.emit(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Binary {
                 binary_operator:
@@ -82,7 +98,7 @@ impl CodeEmitter for asm_gen::Instruction {
                 left_operand.emit_one_byte(writer)?;
                 write!(writer, ", ")?;
                 right_operand.emit(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Binary {
                 binary_operator,
@@ -94,12 +110,17 @@ impl CodeEmitter for asm_gen::Instruction {
                 left_operand.emit(writer)?;
                 write!(writer, ", ")?;
                 right_operand.emit(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Idiv(operand) => {
                 write!(writer, "  idivl ")?;
                 operand.emit(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
+            }
+            asm_gen::Instruction::Imul(operand) => {
+                write!(writer, "  imull ")?;
+                operand.emit(writer)?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Cdq => writeln!(writer, "  cdq")?,
             asm_gen::Instruction::Cmp {
@@ -110,7 +131,17 @@ impl CodeEmitter for asm_gen::Instruction {
                 left_operand.emit(writer)?;
                 write!(writer, ", ")?;
                 right_operand.emit(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
+            }
+            asm_gen::Instruction::Test {
+                left_operand,
+                right_operand,
+            } => {
+                write!(writer, "  testl ")?;
+                left_operand.emit(writer)?;
+                write!(writer, ", ")?;
+                right_operand.emit(writer)?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Jmp(label) => writeln!(writer, "  jmp .L{}", label)?,
             asm_gen::Instruction::JmpCC {
@@ -126,9 +157,42 @@ impl CodeEmitter for asm_gen::Instruction {
                 cond_code.emit(writer)?;
                 write!(writer, " ")?;
                 operand.emit_one_byte(writer)?;
-                write!(writer, "\n")?;
+                writeln!(writer)?;
             }
             asm_gen::Instruction::Label(label) => writeln!(writer, ".L{}:", label)?,
+            asm_gen::Instruction::JumpTable {
+                index,
+                table,
+                targets,
+            } => {
+                //entries are offsets from the table itself so the code stays position independent
+                write!(writer, "  movl ")?;
+                index.emit(writer)?;
+                writeln!(writer, ", %r10d")?;
+                writeln!(writer, "  leaq .L{}(%rip), %r11", table)?;
+                writeln!(writer, "  movslq (%r11,%r10,4), %r10")?;
+                writeln!(writer, "  addq %r11, %r10")?;
+                writeln!(writer, "  jmp *%r10")?;
+                #[cfg(target_os = "linux")]
+                writeln!(writer, "  .section .rodata")?;
+                #[cfg(target_os = "macos")]
+                writeln!(writer, "  .const")?;
+                writeln!(writer, "  .balign 4\n.L{}:", table)?;
+                targets.iter().try_for_each(|target| {
+                    writeln!(writer, "  .long .L{} - .L{}", target, table)
+                })?;
+                writeln!(writer, "  .text")?;
+            }
+            asm_gen::Instruction::Push(register) => {
+                write!(writer, "  pushq ")?;
+                register.emit_eight_byte(writer)?;
+                writeln!(writer)?;
+            }
+            asm_gen::Instruction::Pop(register) => {
+                write!(writer, "  popq ")?;
+                register.emit_eight_byte(writer)?;
+                writeln!(writer)?;
+            }
         }
         Ok(())
     }
@@ -143,10 +207,20 @@ impl CodeEmitter for asm_gen::Operand {
                 asm_gen::Register::DX => write!(writer, "%edx")?,
                 asm_gen::Register::R11 => write!(writer, "%r11d")?,
                 asm_gen::Register::CX => write!(writer, "%ecx")?,
+                asm_gen::Register::BX => write!(writer, "%ebx")?,
+                asm_gen::Register::SI => write!(writer, "%esi")?,
+                asm_gen::Register::DI => write!(writer, "%edi")?,
+                asm_gen::Register::R8 => write!(writer, "%r8d")?,
+                asm_gen::Register::R9 => write!(writer, "%r9d")?,
+                asm_gen::Register::R12 => write!(writer, "%r12d")?,
+                asm_gen::Register::R13 => write!(writer, "%r13d")?,
+                asm_gen::Register::R14 => write!(writer, "%r14d")?,
+                asm_gen::Register::R15 => write!(writer, "%r15d")?,
             },
 
             asm_gen::Operand::Imm(val) => write!(writer, "${}", val)?,
             asm_gen::Operand::Stack(offset) => write!(writer, "{}(%rbp)", offset)?,
+            asm_gen::Operand::StackPointer(offset) => write!(writer, "{}(%rsp)", offset)?,
             asm_gen::Operand::Psuedo(_) => {
                 return Err(Error::CodeEmissionError(
                     "found a pseudo-operator, not supposed to",
@@ -164,11 +238,21 @@ impl asm_gen::Operand {
                 asm_gen::Register::AX => write!(writer, "%al"),
                 asm_gen::Register::R10 => write!(writer, "%r10b"),
                 asm_gen::Register::DX => write!(writer, "%dl"),
-                asm_gen::Register::R11 => write!(writer, "%rllb"),
+                asm_gen::Register::R11 => write!(writer, "%r11b"),
                 asm_gen::Register::CX => write!(writer, "%cl"),
+                asm_gen::Register::BX => write!(writer, "%bl"),
+                asm_gen::Register::SI => write!(writer, "%sil"),
+                asm_gen::Register::DI => write!(writer, "%dil"),
+                asm_gen::Register::R8 => write!(writer, "%r8b"),
+                asm_gen::Register::R9 => write!(writer, "%r9b"),
+                asm_gen::Register::R12 => write!(writer, "%r12b"),
+                asm_gen::Register::R13 => write!(writer, "%r13b"),
+                asm_gen::Register::R14 => write!(writer, "%r14b"),
+                asm_gen::Register::R15 => write!(writer, "%r15b"),
             }?,
             asm_gen::Operand::Imm(val) => write!(writer, "${}", val)?,
             asm_gen::Operand::Stack(offset) => write!(writer, "{}(%rbp)", offset)?,
+            asm_gen::Operand::StackPointer(offset) => write!(writer, "{}(%rsp)", offset)?,
             asm_gen::Operand::Psuedo(_) => {
                 return Err(Error::CodeEmissionError(
                     "found a pseudo-operator, not supposed to",
@@ -180,6 +264,28 @@ impl asm_gen::Operand {
     }
 }
 
+impl asm_gen::Register {
+    fn emit_eight_byte(&self, writer: &mut impl Write) -> Result<()> {
+        match self {
+            asm_gen::Register::AX => write!(writer, "%rax"),
+            asm_gen::Register::R10 => write!(writer, "%r10"),
+            asm_gen::Register::DX => write!(writer, "%rdx"),
+            asm_gen::Register::R11 => write!(writer, "%r11"),
+            asm_gen::Register::CX => write!(writer, "%rcx"),
+            asm_gen::Register::BX => write!(writer, "%rbx"),
+            asm_gen::Register::SI => write!(writer, "%rsi"),
+            asm_gen::Register::DI => write!(writer, "%rdi"),
+            asm_gen::Register::R8 => write!(writer, "%r8"),
+            asm_gen::Register::R9 => write!(writer, "%r9"),
+            asm_gen::Register::R12 => write!(writer, "%r12"),
+            asm_gen::Register::R13 => write!(writer, "%r13"),
+            asm_gen::Register::R14 => write!(writer, "%r14"),
+            asm_gen::Register::R15 => write!(writer, "%r15"),
+        }?;
+        Ok(())
+    }
+}
+
 impl CodeEmitter for asm_gen::UnaryOperator {
     fn emit(&self, writer: &mut impl Write) -> Result<()> {
         match self {
@@ -201,6 +307,7 @@ impl CodeEmitter for asm_gen::BinaryOperator {
             asm_gen::BinaryOperator::BitwiseXor => write!(writer, "  xorl")?,
             asm_gen::BinaryOperator::LeftShift => write!(writer, "  shll")?,
             asm_gen::BinaryOperator::RightShift => write!(writer, "  sarl")?,
+            asm_gen::BinaryOperator::LogicalRightShift => write!(writer, "  shrl")?,
         }
         Ok(())
     }
@@ -215,6 +322,7 @@ impl CodeEmitter for asm_gen::CondCode {
             asm_gen::CondCode::GE => write!(writer, "ge")?,
             asm_gen::CondCode::L => write!(writer, "l")?,
             asm_gen::CondCode::LE => write!(writer, "le")?,
+            asm_gen::CondCode::AE => write!(writer, "ae")?,
         }
         Ok(())
     }
@@ -0,0 +1,114 @@
+//! `--emit` dumps of the intermediate stages. the json layout is documented in json_schema.md
+
+use std::fmt::{self, Display};
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::Result;
+use super::lexer::SpannedToken;
+use crate::error::Error;
+
+/// stages that can be dumped, in pipeline order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub(crate) enum Stage {
+    Tokens,
+    Ast,
+    /// the ast after semantic analysis
+    TypedAst,
+    Tacky,
+    /// the tacky in ssa form, with the phis at the top of each block
+    Ssa,
+    /// graphviz source for the control flow graph of each tacky function
+    CfgDot,
+    Asm,
+}
+
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub(crate) enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Ast => "ast",
+            Stage::TypedAst => "typed-ast",
+            Stage::Tacky => "tacky",
+            Stage::Ssa => "ssa",
+            Stage::CfgDot => "cfg-dot",
+            Stage::Asm => "asm",
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+pub(super) struct Tokens(pub(super) Vec<SpannedToken>);
+
+impl Display for Tokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|token| writeln!(f, "{}", token))
+    }
+}
+
+/// collects the requested stages as the pipeline reaches them. text dumps are written straight away
+/// under a `# <stage>` header, json dumps are gathered into one object written by `finish`
+pub(super) struct Dumps<'a> {
+    stages: &'a [Stage],
+    format: Format,
+    json: Map<String, Value>,
+}
+
+impl<'a> Dumps<'a> {
+    pub(super) fn new(stages: &'a [Stage], format: Format) -> Self {
+        Dumps {
+            stages,
+            format,
+            json: Map::new(),
+        }
+    }
+
+    pub(super) fn wants(&self, stage: Stage) -> bool {
+        self.stages.contains(&stage)
+    }
+
+    /// true once every requested stage has been dumped, so compilation can stop
+    pub(super) fn done_after(&self, stage: Stage) -> bool {
+        !self.stages.is_empty() && self.stages.iter().all(|wanted| *wanted <= stage)
+    }
+
+    pub(super) fn add<T: Serialize + Display>(
+        &mut self,
+        out: &mut impl Write,
+        stage: Stage,
+        ir: &T,
+    ) -> Result<()> {
+        if !self.wants(stage) {
+            return Ok(());
+        }
+        match self.format {
+            Format::Text => write!(out, "# {}\n{}", stage.name(), ir)?,
+            Format::Json => {
+                self.json.insert(
+                    stage.name().to_string(),
+                    serde_json::to_value(ir).expect("ir types always serialize"),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn finish(self, out: &mut impl Write) -> Result<()> {
+        if let Format::Json = self.format {
+            serde_json::to_writer(&mut *out, &self.json).map_err(|e| Error::Io(e.to_string()))?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@ mod tokens;
 use crate::error::{Error, Result};
 use regex::Regex;
 
-pub use tokens::Token;
+pub use tokens::{Span, SpannedToken, Token};
 pub(super) trait Lex {
     fn lex(self) -> Lexer;
 }
@@ -13,6 +13,9 @@ pub(super) struct Lexer {
     pos: usize,
     id: Regex,
     cnst: Regex,
+    line: usize,
+    line_start: usize,
+    span: Span,
 }
 
 impl Lexer {
@@ -23,11 +26,23 @@ impl Lexer {
             id: Regex::new(r"^[a-zA-Z_]\w*\b").expect("this is constant, will work"),
             cnst: Regex::new(r"^[0-9]+\b").expect("this is constant"),
             pos: 0,
+            line: 1,
+            line_start: 0,
+            span: Span::default(),
         }
     }
 
+    /// pairs every token with where it came from in the (preprocessed) source
+    pub(super) fn spanned(self) -> SpannedLexer {
+        SpannedLexer(self)
+    }
+
     fn trim(&mut self) {
         while self.pos < self.input.len() && self.chars[self.pos].is_ascii_whitespace() {
+            if self.chars[self.pos] == b'\n' {
+                self.line += 1;
+                self.line_start = self.pos + 1;
+            }
             self.pos += 1
         }
     }
@@ -165,6 +180,19 @@ impl Lexer {
     }
 }
 
+impl Lexer {
+    //moves past the token just lexed, remembering where it was
+    fn advance(&mut self, pos: usize) {
+        self.span = Span {
+            start: self.pos,
+            end: pos,
+            line: self.line,
+            column: self.pos - self.line_start + 1,
+        };
+        self.pos = pos;
+    }
+}
+
 impl Iterator for Lexer {
     type Item = Result<Token>;
 
@@ -174,25 +202,19 @@ impl Iterator for Lexer {
             return None;
         }
 
-        match self.next_keyword() {
-            Some((token, pos)) => {
-                self.pos = pos;
-                return Some(Ok(token));
-            }
-            None => (),
+        if let Some((token, pos)) = self.next_keyword() {
+            self.advance(pos);
+            return Some(Ok(token));
         };
 
-        match self.next_constant() {
-            Some((token, pos)) => {
-                self.pos = pos;
-                return Some(Ok(token));
-            }
-            None => (),
+        if let Some((token, pos)) = self.next_constant() {
+            self.advance(pos);
+            return Some(Ok(token));
         };
 
         match self.next_operator() {
             Ok((token, pos)) => {
-                self.pos = pos;
+                self.advance(pos);
                 Some(Ok(token))
             }
             Err(e) => {
@@ -203,6 +225,20 @@ impl Iterator for Lexer {
     }
 }
 
+pub(super) struct SpannedLexer(Lexer);
+
+impl Iterator for SpannedLexer {
+    type Item = Result<SpannedToken>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.0.next()?;
+        Some(token.map(|token| SpannedToken {
+            token,
+            span: self.0.span.clone(),
+        }))
+    }
+}
+
 impl Lex for String {
     fn lex(self) -> Lexer {
         Lexer::new(self)
@@ -1,6 +1,10 @@
+use std::fmt;
+
+use serde::Serialize;
 use strum_macros::{Display, EnumString};
 
-#[derive(Debug, Display, EnumString)]
+#[derive(Debug, Display, EnumString, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Token {
     #[strum(to_string = "Identifier: {0}")]
     Identifier(String),
@@ -62,3 +66,25 @@ pub enum Token {
     Default,
     Comma,
 }
+
+/// byte offsets `[start, end)` into the preprocessed source, plus the 1-based line and column of
+/// `start`
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl fmt::Display for SpannedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}\t{}", self.span.line, self.span.column, self.token)
+    }
+}
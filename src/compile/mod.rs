@@ -1,55 +1,191 @@
 mod asm_gen;
+mod cfg;
 mod code_emission;
+mod emit;
 mod lexer;
+mod optimization;
 mod parser;
 mod semantic_analysis;
 mod tacky;
 
 use super::error::Result;
 use asm_gen::asm_gen;
+use cfg::{Cfg, to_dot};
 use code_emission::code_emission;
+use emit::{Dumps, Tokens};
+pub(crate) use emit::{Format, Stage};
 use lexer::Lex;
+pub(crate) use optimization::{OptLevel, Optimizations, Pass};
+use optimization::{optimize, to_ssa};
 use parser::parser;
 use semantic_analysis::semantic_analysis;
 use std::io::Write;
-use tacky::tacky_gen;
+use tacky::{TACKY_COUNTER, interpret, parse_tacky, tacky_gen};
 
+/// which stage (if any) to stop after and dump instead of emitting assembly
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CompileOptions {
+    pub(crate) lex: bool,
+    pub(crate) parse: bool,
+    pub(crate) validate: bool,
+    pub(crate) tacky: bool,
+    pub(crate) codegen: bool,
+    pub(crate) emit: Vec<Stage>,
+    pub(crate) format: Format,
+    pub(crate) optimizations: Optimizations,
+    pub(crate) omit_frame_pointer: bool,
+}
+
+impl CompileOptions {
+    pub(crate) fn stops_early(&self) -> bool {
+        self.lex
+            || self.parse
+            || self.validate
+            || self.tacky
+            || self.codegen
+            || !self.emit.is_empty()
+    }
+}
+
+/// compiles one translation unit. `writer` receives the assembly and `out` receives any stage dumps,
+/// so nothing here touches shared state and units can be compiled on separate threads
 pub(crate) fn compile(
     writer: &mut impl Write,
+    out: &mut impl Write,
     code: String,
-    lex: bool,
-    parse: bool,
-    codegen: bool,
-    tacky: bool,
-    validate: bool,
+    options: &CompileOptions,
 ) -> Result<()> {
+    TACKY_COUNTER.set(0);
+    let mut dumps = Dumps::new(&options.emit, options.format);
+    if dumps.wants(Stage::Tokens) {
+        let tokens = code.clone().lex().spanned().collect::<Result<Vec<_>>>()?;
+        dumps.add(out, Stage::Tokens, &Tokens(tokens))?;
+    }
+    if dumps.done_after(Stage::Tokens) {
+        return dumps.finish(out);
+    }
+
     let toks = code.lex();
-    if lex {
-        println!("{:#?}", toks.collect::<Result<Vec<_>>>()?);
+    if options.lex {
+        writeln!(out, "{:#?}", toks.collect::<Result<Vec<_>>>()?)?;
         return Ok(());
     }
 
     let ast = parser(toks)?;
-    if parse {
-        println!("{:#?}", ast);
+    dumps.add(out, Stage::Ast, &ast)?;
+    if dumps.done_after(Stage::Ast) {
+        return dumps.finish(out);
+    }
+    if options.parse {
+        write!(out, "{}", ast)?;
         return Ok(());
     }
 
     let validated_ast = semantic_analysis(ast)?;
-    if validate {
-        println!("{:#?}", validated_ast);
+    dumps.add(out, Stage::TypedAst, &validated_ast)?;
+    if dumps.done_after(Stage::TypedAst) {
+        return dumps.finish(out);
+    }
+    if options.validate {
+        write!(out, "{}", validated_ast)?;
         return Ok(());
     }
     let tacky_ast = tacky_gen(validated_ast)?;
-    if tacky {
-        println!("{:#?}", tacky_ast);
+    compile_from_tacky(writer, out, tacky_ast, options, dumps)
+}
+
+/// compiles a translation unit written directly in tacky's text format, skipping the front end
+pub(crate) fn compile_tacky(
+    writer: &mut impl Write,
+    out: &mut impl Write,
+    code: String,
+    options: &CompileOptions,
+) -> Result<()> {
+    TACKY_COUNTER.set(0);
+    let tacky_ast = parse_tacky(&code)?;
+    let dumps = Dumps::new(&options.emit, options.format);
+    compile_from_tacky(writer, out, tacky_ast, options, dumps)
+}
+
+/// runs a translation unit through the front end and optimizer and interprets the tacky instead of
+/// emitting assembly. returns what `main` returned
+pub(crate) fn run_tacky(
+    code: String,
+    from_tacky: bool,
+    optimizations: &Optimizations,
+    out: &mut impl Write,
+) -> Result<i32> {
+    TACKY_COUNTER.set(0);
+    let tacky_ast = if from_tacky {
+        parse_tacky(&code)?
+    } else {
+        tacky_gen(semantic_analysis(parser(code.lex())?)?)?
+    };
+    interpret(&optimize(tacky_ast, optimizations, out)?)
+}
+
+fn compile_from_tacky(
+    writer: &mut impl Write,
+    out: &mut impl Write,
+    tacky_ast: tacky::Program,
+    options: &CompileOptions,
+    mut dumps: Dumps,
+) -> Result<()> {
+    let mut tacky_ast = optimize(tacky_ast, &options.optimizations, out)?;
+    dumps.add(out, Stage::Tacky, &tacky_ast)?;
+    if dumps.wants(Stage::Ssa) {
+        let tacky::Program::Program(function) = &tacky_ast;
+        dumps.add(out, Stage::Ssa, &to_ssa(function.clone()))?;
+    }
+    if dumps.wants(Stage::CfgDot) {
+        let tacky::Program::Program(tacky::FunctionDefinition::Function { identifier, body }) =
+            &mut tacky_ast;
+        let cfg = Cfg::new(std::mem::take(body));
+        dumps.add(out, Stage::CfgDot, &to_dot(identifier, &cfg))?;
+        *body = cfg.into_instructions();
+    }
+    if dumps.done_after(Stage::CfgDot) {
+        return dumps.finish(out);
+    }
+    if options.tacky {
+        write!(out, "{}", tacky_ast)?;
         return Ok(());
     }
-    let asm_ast = asm_gen(tacky_ast)?;
-    if codegen {
-        println!("{:#?}", asm_ast);
+    let asm_ast = asm_gen(tacky_ast, options.omit_frame_pointer)?;
+    dumps.add(out, Stage::Asm, &asm_ast)?;
+    if dumps.done_after(Stage::Asm) {
+        return dumps.finish(out);
+    }
+    if options.codegen {
+        write!(out, "{}", asm_ast)?;
         return Ok(());
     }
 
     code_emission(writer, asm_ast)
 }
+
+/// assembles and links `asm` with gcc, runs it and returns its exit status. `name` keeps the files
+/// of tests running at the same time apart
+#[cfg(test)]
+pub(crate) fn run_native(asm: &[u8], name: &str) -> i32 {
+    use std::process::Command;
+
+    let dir = std::env::temp_dir().join(format!("cc-sandler-rs-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).expect("creates a scratch directory");
+    let (source, binary) = (dir.join("out.s"), dir.join("out"));
+    std::fs::write(&source, asm).expect("writes the assembly");
+    let link = Command::new("gcc")
+        .arg(&source)
+        .arg("-o")
+        .arg(&binary)
+        .output()
+        .expect("runs gcc");
+    assert!(
+        link.status.success(),
+        "{}",
+        String::from_utf8_lossy(&link.stderr)
+    );
+    let status = Command::new(&binary).status().expect("runs the binary");
+    let _ = std::fs::remove_dir_all(&dir);
+    status.code().expect("exits normally")
+}
@@ -0,0 +1,124 @@
+//! evaluates operations whose operands are all constants
+//!
+//! folding follows c rather than what the generated code happens to do: anything that would be
+//! undefined behaviour at runtime (signed overflow, dividing by zero, `INT_MIN / -1`, shifting by a
+//! negative amount or the width of an int or more, shifting a negative value left) is left for the
+//! program to do. there are only signed ints so far, so nothing needs to wrap
+
+use crate::compile::tacky::{BinaryOperator, Instruction, UnaryOperator, Value};
+
+pub(super) fn fold_constants(body: Vec<Instruction>) -> Vec<Instruction> {
+    body.into_iter().filter_map(fold_instruction).collect()
+}
+
+/// the folded instruction, or `None` if it can be dropped altogether
+pub(super) fn fold_instruction(instruction: Instruction) -> Option<Instruction> {
+    Some(match instruction {
+        Instruction::UnaryOperator {
+            unary_operator,
+            src: Value::Constant(val),
+            dst,
+        } => match unary(&unary_operator, val) {
+            Some(val) => Instruction::Copy {
+                src: Value::Constant(val),
+                dst,
+            },
+            None => Instruction::UnaryOperator {
+                unary_operator,
+                src: Value::Constant(val),
+                dst,
+            },
+        },
+        Instruction::BinaryOperator {
+            binary_operator,
+            src1: Value::Constant(left),
+            src2: Value::Constant(right),
+            dst,
+        } => match binary(&binary_operator, left, right) {
+            Some(val) => Instruction::Copy {
+                src: Value::Constant(val),
+                dst,
+            },
+            None => Instruction::BinaryOperator {
+                binary_operator,
+                src1: Value::Constant(left),
+                src2: Value::Constant(right),
+                dst,
+            },
+        },
+        Instruction::JumpIfZero {
+            target,
+            condition: Value::Constant(val),
+        } => match val {
+            0 => Instruction::Jump(target),
+            _ => return None,
+        },
+        Instruction::JumpIfNotZero {
+            target,
+            condition: Value::Constant(val),
+        } => match val {
+            0 => return None,
+            _ => Instruction::Jump(target),
+        },
+        Instruction::JumpTable {
+            index: Value::Constant(val),
+            targets,
+            default,
+        } => Instruction::Jump(
+            usize::try_from(val)
+                .ok()
+                .and_then(|index| targets.get(index))
+                .unwrap_or(&default)
+                .clone(),
+        ),
+        instruction => instruction,
+    })
+}
+
+pub(super) fn unary(unary_operator: &UnaryOperator, val: i32) -> Option<i32> {
+    match unary_operator {
+        UnaryOperator::Complement => Some(!val),
+        UnaryOperator::Negate => val.checked_neg(),
+        UnaryOperator::Not => Some((val == 0) as i32),
+        UnaryOperator::Increment => val.checked_add(1),
+        UnaryOperator::Decrement => val.checked_sub(1),
+    }
+}
+
+pub(super) fn binary(binary_operator: &BinaryOperator, left: i32, right: i32) -> Option<i32> {
+    match binary_operator {
+        BinaryOperator::Add => left.checked_add(right),
+        BinaryOperator::Subtract => left.checked_sub(right),
+        BinaryOperator::Multiply => left.checked_mul(right),
+        //checked_div and checked_rem already refuse both division by zero and INT_MIN / -1
+        BinaryOperator::Divide => left.checked_div(right),
+        BinaryOperator::Remainder => left.checked_rem(right),
+        BinaryOperator::BitwiseAnd => Some(left & right),
+        BinaryOperator::BitwiseOr => Some(left | right),
+        BinaryOperator::BitwiseXor => Some(left ^ right),
+        BinaryOperator::LeftShift => {
+            let count = u32::try_from(right)
+                .ok()
+                .filter(|count| *count < i32::BITS)?;
+            if left < 0 {
+                return None;
+            }
+            //the result has to fit without any bits falling off the top
+            i32::try_from((left as i64) << count).ok()
+        }
+        //right shifting a negative value is implementation defined, and gcc defines it as an
+        //arithmetic shift, which is what sarl does too
+        BinaryOperator::RightShift => {
+            let count = u32::try_from(right)
+                .ok()
+                .filter(|count| *count < i32::BITS)?;
+            Some(left >> count)
+        }
+        BinaryOperator::Equal => Some((left == right) as i32),
+        BinaryOperator::NotEqual => Some((left != right) as i32),
+        BinaryOperator::LessThan => Some((left < right) as i32),
+        BinaryOperator::Leq => Some((left <= right) as i32),
+        BinaryOperator::GreaterThan => Some((left > right) as i32),
+        BinaryOperator::Geq => Some((left >= right) as i32),
+    }
+}
@@ -0,0 +1,217 @@
+//! replaces reads of a variable with the value last copied into it, wherever that copy is the only
+//! one that can reach the read
+//!
+//! reaching copies are found with the usual forward dataflow analysis: a copy reaches the start of a
+//! block only if it reaches the end of every predecessor. there are no pointers, statics or function
+//! calls yet, so the only thing that can kill a copy is another write to its source or destination
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::compile::cfg::{Cfg, NodeId};
+use crate::compile::tacky::{Instruction, Value};
+
+/// every value the function mentions, numbered so the sets below can be copied and met without
+/// cloning any names
+struct Values {
+    ids: HashMap<Value, usize>,
+    values: Vec<Value>,
+}
+
+impl Values {
+    fn new(cfg: &Cfg<Instruction>) -> Self {
+        let mut values = Values {
+            ids: HashMap::new(),
+            values: Vec::new(),
+        };
+        for instruction in cfg.blocks.values().flat_map(|block| &block.instructions) {
+            if let Instruction::Copy { src, dst } = instruction {
+                for value in [src, dst] {
+                    if !values.ids.contains_key(value) {
+                        values.ids.insert(value.clone(), values.values.len());
+                        values.values.push(value.clone());
+                    }
+                }
+            }
+        }
+        values
+    }
+
+    /// `None` for a value no copy mentions
+    fn id(&self, value: &Value) -> Option<usize> {
+        self.ids.get(value).copied()
+    }
+}
+
+/// the copies that reach a point, by destination. a copy kills whatever was copied into its
+/// destination before, so at most one into each variable can reach anywhere. they're indexed by
+/// source as well, so a write kills the copies out of the variable without a scan
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ReachingCopies {
+    sources: HashMap<usize, usize>,
+    copies_of: HashMap<usize, HashSet<usize>>,
+}
+
+impl ReachingCopies {
+    /// whether `dst = src` reaches
+    fn holds(&self, src: usize, dst: usize) -> bool {
+        self.sources.get(&dst) == Some(&src)
+    }
+
+    fn insert(&mut self, src: usize, dst: usize) {
+        self.copies_of.entry(src).or_default().insert(dst);
+        self.sources.insert(dst, src);
+    }
+
+    /// forgets every copy into or out of `variable`
+    fn kill(&mut self, variable: usize) {
+        if let Some(src) = self.sources.remove(&variable)
+            && let Some(copies) = self.copies_of.get_mut(&src)
+        {
+            copies.remove(&variable);
+            if copies.is_empty() {
+                self.copies_of.remove(&src);
+            }
+        }
+        for dst in self.copies_of.remove(&variable).into_iter().flatten() {
+            self.sources.remove(&dst);
+        }
+    }
+}
+
+pub(super) fn propagate_copies(cfg: &mut Cfg<Instruction>) {
+    let values = Values::new(cfg);
+    let outs = reaching_copies(cfg, &values);
+    let ids = cfg.blocks.keys().copied().collect::<Vec<_>>();
+    for id in ids {
+        //only a block no path from the entry reaches is left without a visited predecessor
+        let mut reaching = block_in(cfg, &outs, NodeId::Block(id)).unwrap_or_default();
+        let block = cfg.blocks.get_mut(&id).expect("block exists");
+        let instructions = std::mem::take(&mut block.instructions);
+        for instruction in instructions {
+            if let Instruction::Copy { src, dst } = &instruction
+                && let (Some(src), Some(dst)) = (values.id(src), values.id(dst))
+                && (reaching.holds(src, dst) || reaching.holds(dst, src))
+            {
+                //dst already holds src, so the copy changes nothing, reaching copies included
+                continue;
+            }
+            //rewritten against the copies reaching it, but it's the original that kills them
+            let mut rewritten = instruction.clone();
+            rewrite(&mut rewritten, &reaching, &values);
+            transfer(&mut reaching, &instruction, &values);
+            block.instructions.push(rewritten);
+        }
+    }
+}
+
+/// the copies reaching the end of each block, indexed like `cfg.blocks`
+///
+/// a block that hasn't been visited yet counts as every copy reaching its end, so the meet only ever
+/// shrinks sets. one whose predecessors are all still unvisited is skipped until one of them is
+fn reaching_copies(cfg: &Cfg<Instruction>, values: &Values) -> HashMap<usize, ReachingCopies> {
+    let mut outs = HashMap::new();
+    let mut worklist = cfg.blocks.keys().copied().collect::<VecDeque<_>>();
+    while let Some(id) = worklist.pop_front() {
+        let Some(mut reaching) = block_in(cfg, &outs, NodeId::Block(id)) else {
+            continue;
+        };
+        cfg.blocks[&id]
+            .instructions
+            .iter()
+            .for_each(|instruction| transfer(&mut reaching, instruction, values));
+        if outs.get(&id) != Some(&reaching) {
+            outs.insert(id, reaching);
+            for successor in cfg.successors(NodeId::Block(id)) {
+                if let NodeId::Block(successor) = successor
+                    && !worklist.contains(successor)
+                {
+                    worklist.push_back(*successor);
+                }
+            }
+        }
+    }
+    outs
+}
+
+/// the meet over a block's visited predecessors. nothing reaches from the entry. `None` when every
+/// predecessor is still to be visited
+fn block_in(
+    cfg: &Cfg<Instruction>,
+    outs: &HashMap<usize, ReachingCopies>,
+    node: NodeId,
+) -> Option<ReachingCopies> {
+    let predecessors = cfg.predecessors(node);
+    if predecessors.contains(&NodeId::Entry) {
+        return Some(ReachingCopies::default());
+    }
+    let visited = predecessors
+        .iter()
+        .filter_map(|predecessor| match predecessor {
+            NodeId::Block(id) => outs.get(id),
+            NodeId::Entry => unreachable!("handled above"),
+            NodeId::Exit => unreachable!("exit has no successors"),
+        })
+        .collect::<Vec<_>>();
+    if let [only] = visited[..] {
+        return Some(only.clone());
+    }
+    //only what reaches the end of the smallest can reach the end of all of them
+    let Some(smallest) = visited.iter().min_by_key(|out| out.sources.len()) else {
+        return predecessors.is_empty().then(ReachingCopies::default);
+    };
+    let mut met = ReachingCopies::default();
+    for (&dst, &src) in &smallest.sources {
+        if visited.iter().all(|out| out.holds(src, dst)) {
+            met.insert(src, dst);
+        }
+    }
+    Some(met)
+}
+
+fn transfer(reaching: &mut ReachingCopies, instruction: &Instruction, values: &Values) {
+    match instruction {
+        Instruction::Copy { src, dst } => {
+            let (src, dst) = (values.id(src), values.id(dst));
+            let (src, dst) = src.zip(dst).expect("every copy is numbered");
+            if reaching.holds(dst, src) {
+                return;
+            }
+            reaching.kill(dst);
+            reaching.insert(src, dst);
+        }
+        Instruction::UnaryOperator { dst, .. } | Instruction::BinaryOperator { dst, .. } => {
+            //a variable no copy mentions is in no copy to kill
+            if let Some(dst) = values.id(dst) {
+                reaching.kill(dst);
+            }
+        }
+        Instruction::Return(_)
+        | Instruction::Jump(_)
+        | Instruction::JumpIfZero { .. }
+        | Instruction::JumpIfNotZero { .. }
+        | Instruction::JumpTable { .. }
+        | Instruction::Label(_) => (),
+    }
+}
+
+fn rewrite(instruction: &mut Instruction, reaching: &ReachingCopies, values: &Values) {
+    let replace = |value: &mut Value| {
+        if let Some(src) = values.id(value).and_then(|dst| reaching.sources.get(&dst)) {
+            *value = values.values[*src].clone();
+        }
+    };
+    match instruction {
+        Instruction::Return(value) | Instruction::Copy { src: value, .. } => replace(value),
+        Instruction::UnaryOperator { src, .. } => replace(src),
+        Instruction::BinaryOperator { src1, src2, .. } => {
+            replace(src1);
+            replace(src2);
+        }
+        Instruction::JumpIfZero { condition, .. }
+        | Instruction::JumpIfNotZero { condition, .. }
+        | Instruction::JumpTable {
+            index: condition, ..
+        } => replace(condition),
+        Instruction::Jump(_) | Instruction::Label(_) => (),
+    }
+}
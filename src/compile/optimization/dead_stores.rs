@@ -0,0 +1,118 @@
+//! deletes instructions that write a variable nobody reads afterwards
+//!
+//! liveness is the usual backward dataflow analysis: a variable is live at the end of a block if it
+//! is live at the start of any successor. nothing is live at the exit because every variable is a
+//! local whose address is never taken. once statics or `&` exist, those variables have to be live
+//! at the exit and across everything that might read them indirectly
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::compile::cfg::{Cfg, NodeId};
+use crate::compile::tacky::{Instruction, Value};
+
+pub(super) fn eliminate_dead_stores(cfg: &mut Cfg<Instruction>) {
+    let ins = live_variables(cfg);
+    let ids = cfg.blocks.keys().copied().collect::<Vec<_>>();
+    for id in ids {
+        let mut live = block_out(cfg, &ins, NodeId::Block(id));
+        let block = cfg.blocks.get_mut(&id).expect("block exists");
+        let mut instructions = std::mem::take(&mut block.instructions);
+        instructions.reverse();
+        for instruction in instructions {
+            if let Some(Value::Var(dst)) = destination(&instruction)
+                && !live.contains(dst)
+            {
+                continue;
+            }
+            transfer(&mut live, &instruction);
+            block.instructions.push(instruction);
+        }
+        block.instructions.reverse();
+    }
+}
+
+/// the variables live at the start of each block, indexed like `cfg.blocks`
+pub(super) fn live_variables(cfg: &Cfg<Instruction>) -> HashMap<usize, HashSet<String>> {
+    let mut ins = cfg
+        .blocks
+        .keys()
+        .map(|id| (*id, HashSet::new()))
+        .collect::<HashMap<_, _>>();
+
+    let mut worklist = cfg.blocks.keys().rev().copied().collect::<VecDeque<_>>();
+    while let Some(id) = worklist.pop_front() {
+        let mut live = block_out(cfg, &ins, NodeId::Block(id));
+        cfg.blocks[&id]
+            .instructions
+            .iter()
+            .rev()
+            .for_each(|instruction| transfer(&mut live, instruction));
+        if live != ins[&id] {
+            ins.insert(id, live);
+            for predecessor in cfg.predecessors(NodeId::Block(id)) {
+                if let NodeId::Block(predecessor) = predecessor
+                    && !worklist.contains(predecessor)
+                {
+                    worklist.push_back(*predecessor);
+                }
+            }
+        }
+    }
+    ins
+}
+
+/// the union over a block's successors. nothing is live at the exit
+fn block_out(
+    cfg: &Cfg<Instruction>,
+    ins: &HashMap<usize, HashSet<String>>,
+    node: NodeId,
+) -> HashSet<String> {
+    cfg.successors(node)
+        .iter()
+        .flat_map(|successor| match successor {
+            NodeId::Block(id) => ins[id].clone(),
+            NodeId::Exit => HashSet::new(),
+            NodeId::Entry => unreachable!("entry has no predecessors"),
+        })
+        .collect()
+}
+
+fn destination(instruction: &Instruction) -> Option<&Value> {
+    match instruction {
+        Instruction::Copy { dst, .. }
+        | Instruction::UnaryOperator { dst, .. }
+        | Instruction::BinaryOperator { dst, .. } => Some(dst),
+        Instruction::Return(_)
+        | Instruction::Jump(_)
+        | Instruction::JumpIfZero { .. }
+        | Instruction::JumpIfNotZero { .. }
+        | Instruction::JumpTable { .. }
+        | Instruction::Label(_) => None,
+    }
+}
+
+/// steps `live` from after `instruction` to before it
+fn transfer(live: &mut HashSet<String>, instruction: &Instruction) {
+    if let Some(Value::Var(dst)) = destination(instruction) {
+        live.remove(dst);
+    }
+    let mut read = |value: &Value| {
+        if let Value::Var(name) = value {
+            live.insert(name.clone());
+        }
+    };
+    match instruction {
+        Instruction::Return(value) | Instruction::Copy { src: value, .. } => read(value),
+        Instruction::UnaryOperator { src, .. } => read(src),
+        Instruction::BinaryOperator { src1, src2, .. } => {
+            read(src1);
+            read(src2);
+        }
+        Instruction::JumpIfZero { condition, .. }
+        | Instruction::JumpIfNotZero { condition, .. }
+        | Instruction::JumpTable {
+            index: condition, ..
+        } => read(condition),
+        Instruction::Jump(_) | Instruction::Label(_) => (),
+    }
+}
@@ -0,0 +1,150 @@
+//! global value numbering over ssa form
+//!
+//! walks the dominator tree keeping a table of the expressions computed by the blocks above. an
+//! expression that's already in the table was computed by an instruction that dominates this one,
+//! so its result is reused instead: the instruction becomes a copy and every later read of its
+//! destination reads the earlier result directly. a copy's destination is likewise just another
+//! name for its source
+//!
+//! every tacky operator is pure, and a variable can't change under ssa, so nothing ever has to be
+//! dropped from the table early. once calls or stores through pointers exist, expressions that read
+//! memory have to be forgotten past them
+
+use std::collections::HashMap;
+
+use super::ssa::SsaFunction;
+use crate::compile::cfg::{Dominators, NodeId, Visit};
+use crate::compile::tacky::{BinaryOperator, Instruction, UnaryOperator, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Expression {
+    Unary(UnaryOperator, Value),
+    Binary(BinaryOperator, Value, Value),
+}
+
+pub(super) fn number_values(function: &mut SsaFunction) {
+    let dominators = Dominators::new(&function.cfg);
+    let mut numbering = Numbering::default();
+    numbering.visit(function, &dominators);
+
+    //phi sources come in along edges from blocks the walk may not have reached yet, so they're
+    //only rewritten once it's done
+    for phi in function.phis.values_mut().flatten() {
+        for source in phi
+            .sources
+            .iter_mut()
+            .filter_map(|(_, source)| source.as_mut())
+        {
+            *source = numbering.resolve(source);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Numbering {
+    available: HashMap<Expression, Value>,
+    /// variables known to hold the same value as an earlier one
+    replacements: HashMap<String, Value>,
+}
+
+impl Numbering {
+    fn visit(&mut self, function: &mut SsaFunction, dominators: &Dominators) {
+        //the expressions each block on the way down added to the table
+        let mut added = Vec::new();
+        for visit in dominators.walk() {
+            match visit {
+                Visit::Enter(node) => added.push(self.number_block(node, function)),
+                //whatever the block computed isn't available to its siblings in the dominator tree
+                Visit::Exit(_) => {
+                    for expression in added.pop().expect("pushed on entry") {
+                        self.available.remove(&expression);
+                    }
+                }
+            }
+        }
+    }
+
+    /// numbers one block's instructions, returning the expressions it adds to the table
+    fn number_block(&mut self, node: NodeId, function: &mut SsaFunction) -> Vec<Expression> {
+        let mut added = Vec::new();
+        if let NodeId::Block(id) = node {
+            let block = function.cfg.blocks.get_mut(&id).expect("block exists");
+            for instruction in &mut block.instructions {
+                for source in instruction.sources_mut() {
+                    *source = self.resolve(source);
+                }
+                let (expression, dst) = match instruction {
+                    Instruction::Copy {
+                        src,
+                        dst: Value::Var(dst),
+                    } => {
+                        self.replacements.insert(dst.clone(), src.clone());
+                        continue;
+                    }
+                    Instruction::UnaryOperator {
+                        unary_operator,
+                        src,
+                        dst,
+                    } => (Expression::Unary(unary_operator.clone(), src.clone()), dst),
+                    Instruction::BinaryOperator {
+                        binary_operator,
+                        src1,
+                        src2,
+                        dst,
+                    } => {
+                        let (mut left, mut right) = (src1.clone(), src2.clone());
+                        if commutes(binary_operator) && right < left {
+                            std::mem::swap(&mut left, &mut right);
+                        }
+                        (
+                            Expression::Binary(binary_operator.clone(), left, right),
+                            dst,
+                        )
+                    }
+                    _ => continue,
+                };
+                match self.available.get(&expression) {
+                    Some(earlier) => {
+                        let Value::Var(name) = dst else {
+                            unreachable!("only variables are written")
+                        };
+                        self.replacements.insert(name.clone(), earlier.clone());
+                        *instruction = Instruction::Copy {
+                            src: earlier.clone(),
+                            dst: dst.clone(),
+                        };
+                    }
+                    None => {
+                        self.available.insert(expression.clone(), dst.clone());
+                        added.push(expression);
+                    }
+                }
+            }
+        }
+
+        added
+    }
+
+    fn resolve(&self, value: &Value) -> Value {
+        let mut value = value;
+        while let Value::Var(name) = value
+            && let Some(replacement) = self.replacements.get(name)
+        {
+            value = replacement;
+        }
+        value.clone()
+    }
+}
+
+fn commutes(binary_operator: &BinaryOperator) -> bool {
+    matches!(
+        binary_operator,
+        BinaryOperator::Add
+            | BinaryOperator::Multiply
+            | BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseXor
+            | BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+    )
+}
@@ -0,0 +1,228 @@
+//! loop invariant code motion over ssa form
+//!
+//! a back edge is an edge into a block that dominates the block it leaves, and its natural loop is
+//! that header plus everything that reaches the back edge without going through the header. an
+//! instruction in the loop whose operands are constants or are defined outside the loop (or by
+//! instructions already hoisted) gives the same result on every iteration, so it moves to a
+//! preheader: a new block in front of the header that every way into the loop goes through, and no
+//! back edge does
+//!
+//! under ssa each variable has one definition, so moving it up can't clash with another write. the
+//! loop might not have run a hoisted instruction at all, so only ones that can't trap move: division
+//! and remainder stay put unless they divide by a constant other than 0 and -1
+//!
+//! inner loops go first, so what they hoist can carry on out of the loops around them
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use super::ssa::{Phi, SsaFunction};
+use crate::compile::cfg::{BasicBlock, CfgInstruction, Dominators, Flow, NodeId};
+use crate::compile::tacky::{BinaryOperator, Instruction, Value, make_temp_label};
+
+struct Loop {
+    header: usize,
+    label: String,
+    body: BTreeSet<usize>,
+}
+
+pub(super) fn hoist_invariants(function: &mut SsaFunction) {
+    let mut done = HashSet::new();
+    while let Some(natural_loop) = next_loop(function, &done) {
+        done.insert(natural_loop.label.clone());
+        hoist(function, natural_loop);
+    }
+}
+
+/// the smallest loop whose header hasn't been looked at yet
+fn next_loop(function: &SsaFunction, done: &HashSet<String>) -> Option<Loop> {
+    let cfg = &function.cfg;
+    let dominators = Dominators::new(cfg);
+    let mut loops = BTreeMap::<usize, BTreeSet<usize>>::new();
+    for (id, block) in &cfg.blocks {
+        for successor in &block.successors {
+            let NodeId::Block(header) = successor else {
+                continue;
+            };
+            if !dominators.dominates(*successor, NodeId::Block(*id)) {
+                continue;
+            }
+            let body = loops
+                .entry(*header)
+                .or_insert_with(|| BTreeSet::from([*header]));
+            let mut stack = vec![*id];
+            while let Some(id) = stack.pop() {
+                if body.insert(id) {
+                    stack.extend(cfg.predecessors(NodeId::Block(id)).iter().filter_map(
+                        |predecessor| match predecessor {
+                            NodeId::Block(predecessor) => Some(*predecessor),
+                            _ => None,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    loops
+        .into_iter()
+        .filter_map(|(header, body)| {
+            //a header without a label is only ever fallen into, so there's no way to send the
+            //entries to a preheader instead
+            let Flow::Label(label) = cfg.blocks[&header].instructions.first()?.flow() else {
+                return None;
+            };
+            (!done.contains(label)).then(|| Loop {
+                header,
+                label: label.to_string(),
+                body,
+            })
+        })
+        .min_by_key(|natural_loop| natural_loop.body.len())
+}
+
+fn hoist(function: &mut SsaFunction, natural_loop: Loop) {
+    let Loop {
+        header,
+        label,
+        body,
+    } = natural_loop;
+    //the block laid out before the header falls into the preheader instead, so it had better not
+    //be in the loop
+    if let Some((previous, block)) = function.cfg.blocks.range(..header).next_back()
+        && body.contains(previous)
+        && falls_through(block)
+    {
+        return;
+    }
+
+    let mut defined = HashSet::new();
+    for id in &body {
+        for phi in function.phis.get(id).into_iter().flatten() {
+            defined.insert(phi.dst.clone());
+        }
+        for instruction in &function.cfg.blocks[id].instructions {
+            defined.extend(instruction.destination().cloned());
+        }
+    }
+    let mut hoisted = Vec::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in &body {
+            let block = function.cfg.blocks.get_mut(id).expect("block exists");
+            block.instructions = std::mem::take(&mut block.instructions)
+                .into_iter()
+                .filter_map(|instruction| {
+                    if !is_invariant(&instruction, &defined) {
+                        return Some(instruction);
+                    }
+                    defined.remove(instruction.destination().expect("only writes are hoisted"));
+                    hoisted.push(instruction);
+                    changed = true;
+                    None
+                })
+                .collect();
+        }
+    }
+    if hoisted.is_empty() {
+        return;
+    }
+
+    let entries = function
+        .cfg
+        .predecessors(NodeId::Block(header))
+        .iter()
+        .copied()
+        .filter(|predecessor| !matches!(predecessor, NodeId::Block(id) if body.contains(id)))
+        .collect::<Vec<_>>();
+    let preheader_label = make_temp_label(&function.identifier);
+    let mut instructions = vec![Instruction::Label(preheader_label.clone())];
+    instructions.extend(hoisted);
+    function.insert_block(header, instructions);
+    let (preheader, header) = (header, header + 1);
+    let entries = entries
+        .into_iter()
+        .map(|entry| match entry {
+            NodeId::Block(id) if id >= preheader => NodeId::Block(id + 1),
+            entry => entry,
+        })
+        .collect::<Vec<_>>();
+
+    for entry in &entries {
+        function.cfg.remove_edge(*entry, NodeId::Block(header));
+        function.cfg.add_edge(*entry, NodeId::Block(preheader));
+        if let NodeId::Block(id) = entry
+            && let Some(terminator) = function
+                .cfg
+                .blocks
+                .get_mut(id)
+                .and_then(|block| block.instructions.last_mut())
+        {
+            terminator.retarget(&label, &preheader_label);
+        }
+    }
+    function
+        .cfg
+        .add_edge(NodeId::Block(preheader), NodeId::Block(header));
+
+    //the header's phis now get everything from outside the loop through the preheader, which needs
+    //phis of its own wherever the ways in disagree
+    let mut preheader_phis = Vec::new();
+    for phi in function.phis.get_mut(&header).into_iter().flatten() {
+        let (entering, mut sources) = std::mem::take(&mut phi.sources)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(predecessor, _)| entries.contains(predecessor));
+        let value = match entering.as_slice() {
+            [(_, value), rest @ ..] if rest.iter().all(|(_, other)| other == value) => {
+                value.clone()
+            }
+            _ => {
+                let Value::Var(name) = &phi.dst else {
+                    unreachable!("only variables are written")
+                };
+                let dst = Value::Var(format!("{}.pre", name));
+                preheader_phis.push(Phi {
+                    dst: dst.clone(),
+                    sources: entering,
+                });
+                Some(dst)
+            }
+        };
+        sources.push((NodeId::Block(preheader), value));
+        phi.sources = sources;
+    }
+    if !preheader_phis.is_empty() {
+        function.phis.insert(preheader, preheader_phis);
+    }
+}
+
+fn is_invariant(instruction: &Instruction, defined: &HashSet<Value>) -> bool {
+    match instruction {
+        Instruction::Copy { .. } | Instruction::UnaryOperator { .. } => {}
+        Instruction::BinaryOperator {
+            binary_operator: BinaryOperator::Divide | BinaryOperator::Remainder,
+            src2,
+            ..
+        } => {
+            if !matches!(src2, Value::Constant(divisor) if *divisor != 0 && *divisor != -1) {
+                return false;
+            }
+        }
+        Instruction::BinaryOperator { .. } => {}
+        _ => return false,
+    }
+    instruction
+        .sources()
+        .into_iter()
+        .all(|source| !defined.contains(source))
+}
+
+fn falls_through(block: &BasicBlock<Instruction>) -> bool {
+    !matches!(
+        block
+            .instructions
+            .last()
+            .map(|instruction| instruction.flow()),
+        Some(Flow::Jump(_) | Flow::MultiJump(_) | Flow::Return)
+    )
+}
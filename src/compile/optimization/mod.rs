@@ -0,0 +1,189 @@
+//! tacky to tacky optimizations, run between tacky generation and assembly generation
+//!
+//! the enabled passes run in a fixed order, over and over until a whole round leaves the function
+//! unchanged, since each one tends to open up more work for the others. the passes that work on ssa
+//! form run once the others have settled, in a single trip through ssa, and then the others run to
+//! a fixed point again over what comes back
+
+mod constant_folding;
+mod copy_propagation;
+mod dead_stores;
+mod gvn;
+mod licm;
+mod sccp;
+mod ssa;
+mod unreachable_code;
+
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use super::Result;
+use super::cfg::Cfg;
+use super::tacky::{FunctionDefinition, Instruction, Program};
+use constant_folding::fold_constants;
+use copy_propagation::propagate_copies;
+use dead_stores::eliminate_dead_stores;
+use gvn::number_values;
+use licm::hoist_invariants;
+use sccp::propagate_constants;
+pub(super) use ssa::to_ssa;
+use ssa::{SsaFunction, from_ssa};
+use unreachable_code::eliminate_unreachable_code;
+
+/// the optimization passes, in the order each round runs them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Pass {
+    FoldConstants,
+    EliminateUnreachableCode,
+    PropagateCopies,
+    EliminateDeadStores,
+    /// sparse conditional constant propagation, on ssa form
+    Sccp,
+    /// global value numbering, on ssa form
+    Gvn,
+    /// loop invariant code motion, on ssa form
+    Licm,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub(crate) enum OptLevel {
+    #[default]
+    #[value(name = "0")]
+    O0,
+    #[value(name = "1")]
+    O1,
+    #[value(name = "2")]
+    O2,
+}
+
+/// which optimizations to run, and which ones to dump the tacky after
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Optimizations {
+    pub(crate) passes: Vec<Pass>,
+    pub(crate) print_after: Vec<Pass>,
+}
+
+impl Pass {
+    const ALL: [Pass; 7] = [
+        Pass::FoldConstants,
+        Pass::EliminateUnreachableCode,
+        Pass::PropagateCopies,
+        Pass::EliminateDeadStores,
+        Pass::Sccp,
+        Pass::Gvn,
+        Pass::Licm,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Pass::FoldConstants => "fold-constants",
+            Pass::EliminateUnreachableCode => "eliminate-unreachable-code",
+            Pass::PropagateCopies => "propagate-copies",
+            Pass::EliminateDeadStores => "eliminate-dead-stores",
+            Pass::Sccp => "sccp",
+            Pass::Gvn => "gvn",
+            Pass::Licm => "licm",
+        }
+    }
+
+    fn on_ssa(&self) -> bool {
+        matches!(self, Pass::Sccp | Pass::Gvn | Pass::Licm)
+    }
+
+    fn run(&self, body: Vec<Instruction>) -> Vec<Instruction> {
+        let on_cfg = |body, pass: fn(&mut Cfg<Instruction>)| {
+            let mut cfg = Cfg::new(body);
+            pass(&mut cfg);
+            cfg.into_instructions()
+        };
+        match self {
+            Pass::FoldConstants => fold_constants(body),
+            Pass::EliminateUnreachableCode => on_cfg(body, eliminate_unreachable_code),
+            Pass::PropagateCopies => on_cfg(body, propagate_copies),
+            Pass::EliminateDeadStores => on_cfg(body, eliminate_dead_stores),
+            Pass::Sccp | Pass::Gvn | Pass::Licm => unreachable!("runs on ssa form"),
+        }
+    }
+
+    fn run_on_ssa(&self, function: &mut SsaFunction) {
+        match self {
+            Pass::Sccp => propagate_constants(function),
+            Pass::Gvn => number_values(function),
+            Pass::Licm => hoist_invariants(function),
+            _ => unreachable!("runs on plain tacky"),
+        }
+    }
+}
+
+impl OptLevel {
+    pub(crate) fn passes(&self) -> Vec<Pass> {
+        match self {
+            OptLevel::O0 => vec![],
+            OptLevel::O1 => Pass::ALL
+                .into_iter()
+                .filter(|pass| !pass.on_ssa())
+                .collect(),
+            OptLevel::O2 => Pass::ALL.to_vec(),
+        }
+    }
+}
+
+/// runs the enabled passes to a fixed point. `--print-after` dumps go to `out`
+pub(super) fn optimize(
+    program: Program,
+    optimizations: &Optimizations,
+    out: &mut impl Write,
+) -> Result<Program> {
+    let Program::Program(mut function) = program;
+    let (ssa_passes, passes) = Pass::ALL
+        .into_iter()
+        .filter(|pass| optimizations.passes.contains(pass))
+        .partition::<Vec<_>, _>(Pass::on_ssa);
+
+    let mut round = 1;
+    run_to_fixed_point(&mut function, &passes, optimizations, &mut round, out)?;
+    if !ssa_passes.is_empty() {
+        let mut ssa = to_ssa(function);
+        for pass in &ssa_passes {
+            pass.run_on_ssa(&mut ssa);
+            if optimizations.print_after.contains(pass) {
+                write!(out, "# after {} (round {})\n{}", pass.name(), round, ssa)?;
+            }
+        }
+        round += 1;
+        function = from_ssa(ssa);
+        run_to_fixed_point(&mut function, &passes, optimizations, &mut round, out)?;
+    }
+    Ok(Program::Program(function))
+}
+
+fn run_to_fixed_point(
+    function: &mut FunctionDefinition,
+    passes: &[Pass],
+    optimizations: &Optimizations,
+    round: &mut usize,
+    out: &mut impl Write,
+) -> Result<()> {
+    while !passes.is_empty() {
+        let before = function.clone();
+        for pass in passes {
+            let FunctionDefinition::Function { body, .. } = function;
+            *body = pass.run(std::mem::take(body));
+            if optimizations.print_after.contains(pass) {
+                write!(
+                    out,
+                    "# after {} (round {})\n{}",
+                    pass.name(),
+                    round,
+                    function
+                )?;
+            }
+        }
+        *round += 1;
+        if *function == before {
+            break;
+        }
+    }
+    Ok(())
+}
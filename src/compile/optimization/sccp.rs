@@ -0,0 +1,335 @@
+//! sparse conditional constant propagation (wegman and zadeck) over ssa form
+//!
+//! every variable starts out unknown and only gets worse: unknown, then one constant, then
+//! anything. blocks start out unreachable, and a branch only makes the edges it can actually take
+//! reachable, so a phi ignores whatever comes in along an edge that's never taken. that finds
+//! constants plain folding can't, like a variable that's set to the same thing on every path that
+//! really happens, and it throws away the paths that don't
+//!
+//! a branch on a value that's still unknown once everything settles could go either way (it reads
+//! a variable that's never been written), so it's treated as taking every edge
+
+use std::collections::{HashMap, HashSet};
+
+use super::constant_folding::{binary, fold_instruction, unary};
+use super::ssa::SsaFunction;
+use crate::compile::cfg::{CfgInstruction, Flow, NodeId};
+use crate::compile::tacky::{Instruction, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    Unknown,
+    Constant(i32),
+    Overdefined,
+}
+
+/// where a variable is read
+#[derive(Debug, Clone, Copy)]
+enum Use {
+    Phi(usize, usize),
+    Instruction(usize, usize),
+}
+
+pub(super) fn propagate_constants(function: &mut SsaFunction) {
+    let analysis = Analysis::run(function);
+    let SsaFunction { cfg, phis, .. } = function;
+
+    let ids = cfg.blocks.keys().copied().collect::<Vec<_>>();
+    for id in &ids {
+        if !analysis.executable.contains(id) {
+            cfg.remove_block(*id);
+            phis.remove(id);
+        }
+    }
+    for id in cfg.blocks.keys().copied().collect::<Vec<_>>() {
+        let node = NodeId::Block(id);
+        for successor in cfg.successors(node).to_vec() {
+            if successor != NodeId::Exit && !analysis.edges.contains(&(node, successor)) {
+                cfg.remove_edge(node, successor);
+            }
+        }
+    }
+
+    for (id, block_phis) in phis.iter_mut() {
+        block_phis.retain(|phi| analysis.constant(&phi.dst).is_none());
+        for phi in block_phis {
+            phi.sources.retain(|(predecessor, _)| {
+                analysis.edges.contains(&(*predecessor, NodeId::Block(*id)))
+            });
+            for source in phi
+                .sources
+                .iter_mut()
+                .filter_map(|(_, source)| source.as_mut())
+            {
+                analysis.substitute(source);
+            }
+        }
+    }
+    for block in cfg.blocks.values_mut() {
+        block.instructions = std::mem::take(&mut block.instructions)
+            .into_iter()
+            .filter(|instruction| {
+                instruction
+                    .destination()
+                    .is_none_or(|dst| analysis.constant(dst).is_none())
+            })
+            .filter_map(|mut instruction| {
+                instruction
+                    .sources_mut()
+                    .into_iter()
+                    .for_each(|source| analysis.substitute(source));
+                fold_instruction(instruction)
+            })
+            .collect();
+    }
+}
+
+struct Analysis {
+    values: HashMap<String, Lattice>,
+    /// variables some instruction or phi writes. anything else is read without ever being written
+    defined: HashSet<String>,
+    executable: HashSet<usize>,
+    edges: HashSet<(NodeId, NodeId)>,
+}
+
+impl Analysis {
+    fn run(function: &SsaFunction) -> Self {
+        let SsaFunction { cfg, phis, .. } = function;
+        let mut uses = HashMap::<String, Vec<Use>>::new();
+        let mut defined = HashSet::new();
+        for (id, block_phis) in phis {
+            for (i, phi) in block_phis.iter().enumerate() {
+                if let Value::Var(dst) = &phi.dst {
+                    defined.insert(dst.clone());
+                }
+                for (_, source) in &phi.sources {
+                    if let Some(Value::Var(name)) = source {
+                        uses.entry(name.clone()).or_default().push(Use::Phi(*id, i));
+                    }
+                }
+            }
+        }
+        for (id, block) in &cfg.blocks {
+            for (i, instruction) in block.instructions.iter().enumerate() {
+                if let Some(Value::Var(dst)) = instruction.destination() {
+                    defined.insert(dst.clone());
+                }
+                for source in instruction.sources() {
+                    if let Value::Var(name) = source {
+                        uses.entry(name.clone())
+                            .or_default()
+                            .push(Use::Instruction(*id, i));
+                    }
+                }
+            }
+        }
+        let labels = cfg
+            .blocks
+            .iter()
+            .filter_map(|(id, block)| match block.instructions.first()?.flow() {
+                Flow::Label(label) => Some((label.to_string(), *id)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let ids = cfg.blocks.keys().copied().collect::<Vec<_>>();
+        let next_block = ids
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect::<HashMap<_, _>>();
+
+        let mut analysis = Analysis {
+            values: HashMap::new(),
+            defined,
+            executable: HashSet::new(),
+            edges: HashSet::new(),
+        };
+        let mut flow_worklist = cfg
+            .successors(NodeId::Entry)
+            .iter()
+            .map(|successor| (NodeId::Entry, *successor))
+            .collect::<Vec<_>>();
+        let mut ssa_worklist = Vec::new();
+        loop {
+            let mut visits = Vec::new();
+            if let Some(edge) = flow_worklist.pop() {
+                let (_, NodeId::Block(id)) = edge else {
+                    continue;
+                };
+                if !analysis.edges.insert(edge) {
+                    continue;
+                }
+                visits.extend((0..phis.get(&id).map_or(0, Vec::len)).map(|i| Use::Phi(id, i)));
+                if analysis.executable.insert(id) {
+                    visits.extend(
+                        (0..cfg.blocks[&id].instructions.len()).map(|i| Use::Instruction(id, i)),
+                    );
+                }
+            } else if let Some(variable) = ssa_worklist.pop() {
+                visits.extend(uses.get(&variable).into_iter().flatten().filter(
+                    |visit| match visit {
+                        Use::Phi(id, _) | Use::Instruction(id, _) => {
+                            analysis.executable.contains(id)
+                        }
+                    },
+                ));
+            } else {
+                break;
+            }
+
+            for visit in visits {
+                match visit {
+                    Use::Phi(id, i) => {
+                        let phi = &phis[&id][i];
+                        let value = phi
+                            .sources
+                            .iter()
+                            .filter(|(predecessor, _)| {
+                                analysis.edges.contains(&(*predecessor, NodeId::Block(id)))
+                            })
+                            .fold(Lattice::Unknown, |value, (_, source)| {
+                                meet(
+                                    value,
+                                    source
+                                        .as_ref()
+                                        .map_or(Lattice::Unknown, |source| analysis.value(source)),
+                                )
+                            });
+                        if analysis.update(&phi.dst, value) {
+                            ssa_worklist.extend(name(&phi.dst));
+                        }
+                    }
+                    Use::Instruction(id, i) => {
+                        let block = &cfg.blocks[&id];
+                        let instruction = &block.instructions[i];
+                        if let Some(dst) = instruction.destination()
+                            && analysis.update(dst, analysis.evaluate(instruction))
+                        {
+                            ssa_worklist.extend(name(dst));
+                        }
+                        //only the end of a block decides where control goes next
+                        if i + 1 != block.instructions.len() {
+                            continue;
+                        }
+                        let node = NodeId::Block(id);
+                        let fallthrough = || next_block.get(&id).map(|next| NodeId::Block(*next));
+                        let label = |label: &str| NodeId::Block(labels[label]);
+                        let taken = match instruction {
+                            Instruction::JumpIfZero { target, condition }
+                            | Instruction::JumpIfNotZero { target, condition } => {
+                                match analysis.value(condition) {
+                                    Lattice::Constant(val) => {
+                                        let jumps = (val == 0)
+                                            == matches!(
+                                                instruction,
+                                                Instruction::JumpIfZero { .. }
+                                            );
+                                        if jumps {
+                                            Some(vec![label(target)])
+                                        } else {
+                                            Some(fallthrough().into_iter().collect())
+                                        }
+                                    }
+                                    _ => None,
+                                }
+                            }
+                            Instruction::JumpTable {
+                                index,
+                                targets,
+                                default,
+                            } => match analysis.value(index) {
+                                Lattice::Constant(val) => Some(vec![label(
+                                    usize::try_from(val)
+                                        .ok()
+                                        .and_then(|index| targets.get(index))
+                                        .unwrap_or(default),
+                                )]),
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        let taken = taken.unwrap_or_else(|| cfg.successors(node).to_vec());
+                        flow_worklist.extend(taken.into_iter().map(|successor| (node, successor)));
+                    }
+                }
+            }
+        }
+        analysis
+    }
+
+    fn value(&self, value: &Value) -> Lattice {
+        match value {
+            Value::Constant(val) => Lattice::Constant(*val),
+            Value::Var(name) => match self.values.get(name) {
+                Some(value) => *value,
+                None if self.defined.contains(name) => Lattice::Unknown,
+                None => Lattice::Overdefined,
+            },
+        }
+    }
+
+    /// lowers `dst` to `value`, returning whether that changed anything
+    fn update(&mut self, dst: &Value, value: Lattice) -> bool {
+        let Value::Var(dst) = dst else {
+            unreachable!("only variables are written")
+        };
+        self.values.insert(dst.clone(), value) != Some(value) && value != Lattice::Unknown
+    }
+
+    fn evaluate(&self, instruction: &Instruction) -> Lattice {
+        match instruction {
+            Instruction::Copy { src, .. } => self.value(src),
+            Instruction::UnaryOperator {
+                unary_operator,
+                src,
+                ..
+            } => match self.value(src) {
+                Lattice::Constant(val) => {
+                    unary(unary_operator, val).map_or(Lattice::Overdefined, Lattice::Constant)
+                }
+                value => value,
+            },
+            Instruction::BinaryOperator {
+                binary_operator,
+                src1,
+                src2,
+                ..
+            } => match (self.value(src1), self.value(src2)) {
+                (Lattice::Constant(left), Lattice::Constant(right)) => {
+                    binary(binary_operator, left, right)
+                        .map_or(Lattice::Overdefined, Lattice::Constant)
+                }
+                (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+                _ => Lattice::Unknown,
+            },
+            _ => unreachable!("only copies and operators write a variable"),
+        }
+    }
+
+    fn constant(&self, value: &Value) -> Option<i32> {
+        match self.value(value) {
+            Lattice::Constant(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    fn substitute(&self, value: &mut Value) {
+        if let Some(val) = self.constant(value) {
+            *value = Value::Constant(val);
+        }
+    }
+}
+
+fn meet(a: Lattice, b: Lattice) -> Lattice {
+    match (a, b) {
+        (Lattice::Unknown, other) | (other, Lattice::Unknown) => other,
+        (Lattice::Constant(a), Lattice::Constant(b)) if a == b => Lattice::Constant(a),
+        _ => Lattice::Overdefined,
+    }
+}
+
+fn name(value: &Value) -> Option<String> {
+    match value {
+        Value::Var(name) => Some(name.clone()),
+        Value::Constant(_) => None,
+    }
+}
@@ -0,0 +1,192 @@
+//! cytron et al: phis go on the iterated dominance frontier of each variable's definitions, then a
+//! walk down the dominator tree gives every definition a fresh name and points each use at the
+//! nearest one above it
+//!
+//! phis are only placed where the variable is live on the way in (pruned ssa). a phi for a dead
+//! variable would merge versions that were never written on some of the ways in, and destruction
+//! would turn it into copies of uninitialised variables
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::{Phi, SsaFunction};
+use crate::compile::cfg::{Cfg, Dominators, NodeId, Visit};
+use crate::compile::optimization::dead_stores::live_variables;
+use crate::compile::tacky::{FunctionDefinition, Instruction, Value};
+
+pub(crate) fn to_ssa(function: FunctionDefinition) -> SsaFunction {
+    let FunctionDefinition::Function { identifier, body } = function;
+    let mut cfg = Cfg::new(body);
+    //unreachable blocks have no dominators, and nothing they do can matter
+    let reachable = cfg.reachable();
+    let unreachable = cfg
+        .blocks
+        .keys()
+        .copied()
+        .filter(|id| !reachable.contains(&NodeId::Block(*id)))
+        .collect::<Vec<_>>();
+    unreachable.into_iter().for_each(|id| cfg.remove_block(id));
+
+    let dominators = Dominators::new(&cfg);
+    let originals = place_phis(&cfg, &dominators);
+    let mut phis = originals
+        .iter()
+        .map(|(id, variables)| {
+            let phis = variables
+                .iter()
+                .map(|variable| Phi {
+                    dst: Value::Var(variable.clone()),
+                    sources: Vec::new(),
+                })
+                .collect();
+            (*id, phis)
+        })
+        .collect();
+    let mut renamer = Renamer::default();
+    renamer.rename(&mut cfg, &mut phis, &originals, &dominators);
+    SsaFunction {
+        identifier,
+        cfg,
+        phis,
+    }
+}
+
+/// the variables each block needs a phi for
+fn place_phis(cfg: &Cfg<Instruction>, dominators: &Dominators) -> BTreeMap<usize, Vec<String>> {
+    let live = live_variables(cfg);
+    let mut definitions = BTreeMap::<String, BTreeSet<usize>>::new();
+    for (id, block) in &cfg.blocks {
+        for instruction in &block.instructions {
+            if let Some(Value::Var(name)) = instruction.destination() {
+                definitions.entry(name.clone()).or_default().insert(*id);
+            }
+        }
+    }
+
+    let mut placed = BTreeMap::<usize, Vec<String>>::new();
+    for (variable, blocks) in definitions {
+        let mut worklist = blocks.iter().copied().collect::<Vec<_>>();
+        let mut visited = HashSet::new();
+        while let Some(id) = worklist.pop() {
+            for frontier in dominators.frontier(NodeId::Block(id)) {
+                let NodeId::Block(frontier) = frontier else {
+                    continue;
+                };
+                if !visited.insert(frontier) {
+                    continue;
+                }
+                //the frontier is worked out in full either way, so a dead join doesn't hide a live
+                //one further on
+                if live[&frontier].contains(&variable) {
+                    placed.entry(frontier).or_default().push(variable.clone());
+                }
+                if !blocks.contains(&frontier) {
+                    worklist.push(frontier);
+                }
+            }
+        }
+    }
+    placed
+}
+
+#[derive(Default)]
+struct Renamer {
+    /// the current name of each variable, innermost last
+    stacks: HashMap<String, Vec<String>>,
+    versions: HashMap<String, usize>,
+}
+
+impl Renamer {
+    fn fresh(&mut self, variable: &str) -> String {
+        let version = self.versions.entry(variable.to_string()).or_default();
+        *version += 1;
+        let name = format!("{}.v{}", variable, version);
+        self.stacks
+            .entry(variable.to_string())
+            .or_default()
+            .push(name.clone());
+        name
+    }
+
+    fn current(&self, variable: &str) -> Option<Value> {
+        self.stacks
+            .get(variable)
+            .and_then(|stack| stack.last())
+            .map(|name| Value::Var(name.clone()))
+    }
+
+    fn rename(
+        &mut self,
+        cfg: &mut Cfg<Instruction>,
+        phis: &mut BTreeMap<usize, Vec<Phi>>,
+        originals: &BTreeMap<usize, Vec<String>>,
+        dominators: &Dominators,
+    ) {
+        //the variables each block on the way down pushed names for
+        let mut defined = Vec::new();
+        for visit in dominators.walk() {
+            match visit {
+                Visit::Enter(node) => defined.push(self.rename_block(node, cfg, phis, originals)),
+                Visit::Exit(_) => {
+                    for variable in defined.pop().expect("pushed on entry") {
+                        self.stacks
+                            .get_mut(&variable)
+                            .expect("pushed on entry")
+                            .pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// renames one block and fills in its successors' phi sources. returns the variables it pushed
+    /// a new name for, which come off again once its subtree is done
+    fn rename_block(
+        &mut self,
+        node: NodeId,
+        cfg: &mut Cfg<Instruction>,
+        phis: &mut BTreeMap<usize, Vec<Phi>>,
+        originals: &BTreeMap<usize, Vec<String>>,
+    ) -> Vec<String> {
+        let mut defined = Vec::new();
+        if let NodeId::Block(id) = node {
+            for (phi, variable) in phis
+                .get_mut(&id)
+                .into_iter()
+                .flatten()
+                .zip(originals.get(&id).into_iter().flatten())
+            {
+                phi.dst = Value::Var(self.fresh(variable));
+                defined.push(variable.clone());
+            }
+            let block = cfg.blocks.get_mut(&id).expect("block exists");
+            for instruction in &mut block.instructions {
+                for source in instruction.sources_mut() {
+                    if let Value::Var(name) = source
+                        && let Some(current) = self.current(name)
+                    {
+                        *source = current;
+                    }
+                }
+                if let Some(Value::Var(name)) = instruction.destination_mut() {
+                    defined.push(name.clone());
+                    *name = self.fresh(name);
+                }
+            }
+        }
+
+        for successor in cfg.successors(node).to_vec() {
+            let NodeId::Block(successor) = successor else {
+                continue;
+            };
+            for (phi, variable) in phis
+                .get_mut(&successor)
+                .into_iter()
+                .flatten()
+                .zip(originals.get(&successor).into_iter().flatten())
+            {
+                phi.sources.push((node, self.current(variable)));
+            }
+        }
+        defined
+    }
+}
@@ -0,0 +1,169 @@
+//! out of ssa form by turning each phi into copies at the end of its predecessors
+//!
+//! a predecessor that branches somewhere else as well can't hold the copies for just one of its
+//! edges, so that edge gets split: the branch is pointed at a new block holding the copies, which
+//! then jumps on to the phi's block. the copies on one edge happen in parallel, so they're put in
+//! an order where nothing is overwritten before it's read, breaking cycles with a temporary
+//!
+//! a source that nothing ever writes (an undef, or a version whose definition an optimization
+//! deleted, or a phi merging only those) gets no copy. it would only read an uninitialised variable
+
+use std::collections::{HashMap, HashSet};
+
+use super::SsaFunction;
+use crate::compile::cfg::{CfgInstruction, Flow, NodeId};
+use crate::compile::tacky::{
+    FunctionDefinition, Instruction, Value, make_temp_identifier, make_temp_label,
+};
+
+pub(crate) fn from_ssa(function: SsaFunction) -> FunctionDefinition {
+    let written = written_variables(&function);
+    let SsaFunction {
+        identifier,
+        mut cfg,
+        phis,
+    } = function;
+
+    //the (dst, src) copies each edge has to make
+    let mut edge_copies = HashMap::<(usize, usize), Vec<(Value, Value)>>::new();
+    for (id, block_phis) in phis {
+        for phi in block_phis {
+            for (predecessor, source) in phi.sources {
+                if let (NodeId::Block(predecessor), Some(source)) = (predecessor, source)
+                    && is_written(&source, &written)
+                {
+                    edge_copies
+                        .entry((predecessor, id))
+                        .or_default()
+                        .push((phi.dst.clone(), source));
+                }
+            }
+        }
+    }
+
+    let labels = cfg
+        .blocks
+        .iter()
+        .filter_map(
+            |(id, block)| match block.instructions.first().map(|i| i.flow()) {
+                Some(Flow::Label(label)) => Some((*id, label.to_string())),
+                _ => None,
+            },
+        )
+        .collect::<HashMap<_, _>>();
+
+    let ids = cfg.blocks.keys().copied().collect::<Vec<_>>();
+    let mut body = Vec::new();
+    let mut split_edges = Vec::new();
+    for (i, id) in ids.iter().enumerate() {
+        let successors = cfg.successors(NodeId::Block(*id)).to_vec();
+        let mut instructions =
+            std::mem::take(&mut cfg.blocks.get_mut(id).expect("block exists").instructions);
+        let branches = matches!(
+            instructions.last().map(|i| i.flow()),
+            Some(Flow::ConditionalJump(_) | Flow::MultiJump(_))
+        );
+        for successor in successors {
+            let NodeId::Block(successor) = successor else {
+                continue;
+            };
+            let Some(copies) = edge_copies.remove(&(*id, successor)) else {
+                continue;
+            };
+            let copies = sequentialize(copies, &identifier);
+            if !branches {
+                //the only way out, so the copies go just before the jump, if there is one
+                let at = match instructions.last().map(|i| i.flow()) {
+                    Some(Flow::Jump(_)) => instructions.len() - 1,
+                    _ => instructions.len(),
+                };
+                instructions.splice(at..at, copies);
+                continue;
+            }
+            if let Some(label) = labels.get(&successor) {
+                let terminator = instructions.last_mut().expect("block branches");
+                let split = make_temp_label(&identifier);
+                if terminator.retarget(label, &split) {
+                    split_edges.push(Instruction::Label(split));
+                    split_edges.extend(copies.iter().cloned());
+                    split_edges.push(Instruction::Jump(label.clone()));
+                }
+            }
+            if ids.get(i + 1) == Some(&successor) {
+                instructions.extend(copies);
+            }
+        }
+        body.extend(instructions);
+    }
+    body.extend(split_edges);
+    FunctionDefinition::Function { identifier, body }
+}
+
+/// every variable that gets written on some path: the destinations of instructions, and phis with a
+/// constant or another such variable as a source
+fn written_variables(function: &SsaFunction) -> HashSet<Value> {
+    let mut written = function
+        .cfg
+        .blocks
+        .values()
+        .flat_map(|block| &block.instructions)
+        .filter_map(|instruction| instruction.destination().cloned())
+        .collect::<HashSet<_>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for phi in function.phis.values().flatten() {
+            if !written.contains(&phi.dst)
+                && phi.sources.iter().any(|(_, source)| {
+                    source
+                        .as_ref()
+                        .is_some_and(|source| is_written(source, &written))
+                })
+            {
+                written.insert(phi.dst.clone());
+                changed = true;
+            }
+        }
+    }
+    written
+}
+
+fn is_written(value: &Value, written: &HashSet<Value>) -> bool {
+    matches!(value, Value::Constant(_)) || written.contains(value)
+}
+
+/// orders parallel (dst, src) copies so every source is read before it's overwritten
+fn sequentialize(copies: Vec<(Value, Value)>, function_name: &str) -> Vec<Instruction> {
+    let mut pending = copies
+        .into_iter()
+        .filter(|(dst, src)| dst != src)
+        .collect::<Vec<_>>();
+    let mut instructions = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(dst, _)| pending.iter().all(|(_, src)| src != dst));
+        match ready {
+            Some(i) => {
+                let (dst, src) = pending.remove(i);
+                instructions.push(Instruction::Copy { src, dst });
+            }
+            //every destination left is still to be read, so they're all in cycles. saving one
+            //frees it up to be written
+            None => {
+                let saved = pending[0].0.clone();
+                let temp = Value::Var(make_temp_identifier(function_name));
+                instructions.push(Instruction::Copy {
+                    src: saved.clone(),
+                    dst: temp.clone(),
+                });
+                for (_, src) in &mut pending {
+                    if *src == saved {
+                        *src = temp.clone();
+                    }
+                }
+            }
+        }
+    }
+    instructions
+}
@@ -0,0 +1,134 @@
+//! static single assignment form over the control flow graph
+//!
+//! every variable is written by exactly one instruction. where different definitions of a variable
+//! meet, a phi at the top of the block picks the one for the edge control came in on. the phis
+//! live next to the blocks rather than in the tacky itself, since they only mean anything while the
+//! graph they refer to exists
+
+mod construction;
+mod destruction;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use crate::compile::cfg::{Cfg, NodeId};
+use crate::compile::tacky::{Instruction, Value};
+
+pub(crate) use construction::to_ssa;
+pub(crate) use destruction::from_ssa;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Phi {
+    pub(crate) dst: Value,
+    /// the value for each predecessor. `None` when the variable isn't defined along that edge
+    pub(crate) sources: Vec<(NodeId, Option<Value>)>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SsaFunction {
+    pub(crate) identifier: String,
+    pub(crate) cfg: Cfg<Instruction>,
+    /// the phis at the top of each block, indexed like `cfg.blocks`
+    pub(crate) phis: BTreeMap<usize, Vec<Phi>>,
+}
+
+impl SsaFunction {
+    /// puts a new block with no edges or phis in front of block `at`, renumbering it and the blocks
+    /// after it
+    pub(crate) fn insert_block(&mut self, at: usize, instructions: Vec<Instruction>) {
+        self.cfg.insert_block(at, instructions);
+        let shift = |id: usize| if id >= at { id + 1 } else { id };
+        self.phis = std::mem::take(&mut self.phis)
+            .into_iter()
+            .map(|(id, mut phis)| {
+                for phi in &mut phis {
+                    for (predecessor, _) in &mut phi.sources {
+                        if let NodeId::Block(id) = predecessor {
+                            *id = shift(*id);
+                        }
+                    }
+                }
+                (shift(id), phis)
+            })
+            .collect();
+    }
+}
+
+impl Display for SsaFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function {} {{", self.identifier)?;
+        for (id, block) in &self.cfg.blocks {
+            writeln!(f, "  # block {}", id)?;
+            for phi in self.phis.get(id).into_iter().flatten() {
+                writeln!(f, "{}", phi)?;
+            }
+            block
+                .instructions
+                .iter()
+                .try_for_each(|instruction| writeln!(f, "{}", instruction))?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Phi {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "    {} = phi", self.dst)?;
+        for (i, (predecessor, source)) in self.sources.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            let predecessor = match predecessor {
+                NodeId::Entry => "entry".to_string(),
+                NodeId::Block(id) => format!("block {}", id),
+                NodeId::Exit => unreachable!("exit has no successors"),
+            };
+            match source {
+                Some(value) => write!(f, "{}[{}: {}]", separator, predecessor, value)?,
+                None => write!(f, "{}[{}: undef]", separator, predecessor)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::sink;
+
+    use crate::compile::{OptLevel, Optimizations, Pass, run_tacky};
+
+    /// the `&&` temporary is only written on the `?:`'s false arm, and is dead at the loop header
+    /// and the join after the `?:`. phis there would copy it from where it was never written
+    const AND_IN_CONDITIONAL_ARM: &str = "int main(void) {
+        int a = 4;
+        int b = 0;
+        for (int i = 0; i < 3; i = i + 1)
+            b = a ? 1 : (a && a);
+        return b;
+    }";
+
+    #[test]
+    fn and_temporary_dead_at_loop_header_gets_no_phi() {
+        let levels = [OptLevel::O0, OptLevel::O2].map(|level| level.passes());
+        let passes = [Pass::Sccp, Pass::Gvn, Pass::Licm].map(|pass| vec![pass]);
+        for passes in levels.into_iter().chain(passes) {
+            let optimizations = Optimizations {
+                passes: passes.clone(),
+                print_after: vec![],
+            };
+            let result = run_tacky(
+                AND_IN_CONDITIONAL_ARM.to_string(),
+                false,
+                &optimizations,
+                &mut sink(),
+            );
+            assert_eq!(
+                result.expect("reads nothing uninitialised"),
+                1,
+                "{:?}",
+                passes
+            );
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! removes blocks control can never reach, jumps to the block that follows anyway, and labels that
+//! nothing jumps to
+
+use std::collections::HashSet;
+
+use crate::compile::cfg::{Cfg, CfgInstruction, Flow, NodeId};
+
+pub(super) fn eliminate_unreachable_code<I: CfgInstruction>(cfg: &mut Cfg<I>) {
+    remove_unreachable_blocks(cfg);
+    remove_useless_jumps(cfg);
+    remove_useless_labels(cfg);
+}
+
+fn remove_unreachable_blocks<I>(cfg: &mut Cfg<I>) {
+    let reachable = cfg.reachable();
+    let unreachable = cfg
+        .blocks
+        .keys()
+        .copied()
+        .filter(|id| !reachable.contains(&NodeId::Block(*id)))
+        .collect::<Vec<_>>();
+    unreachable.into_iter().for_each(|id| cfg.remove_block(id));
+}
+
+/// a jump whose target is the next block does nothing that falling through wouldn't. a conditional
+/// jump there goes to the same place either way, and tacky values have no side effects, so it can go
+/// too. the edges stay the same
+fn remove_useless_jumps<I: CfgInstruction>(cfg: &mut Cfg<I>) {
+    let ids = cfg.blocks.keys().copied().collect::<Vec<_>>();
+    for (id, next) in ids.iter().zip(ids.iter().skip(1)) {
+        let next_label = match cfg.blocks[next].instructions.first().map(|i| i.flow()) {
+            Some(Flow::Label(label)) => label.to_string(),
+            _ => continue,
+        };
+        let block = cfg.blocks.get_mut(id).expect("block exists");
+        let useless = match block.instructions.last().map(|i| i.flow()) {
+            Some(Flow::Jump(target) | Flow::ConditionalJump(target)) => target == next_label,
+            _ => false,
+        };
+        if useless {
+            block.instructions.pop();
+        }
+    }
+}
+
+fn remove_useless_labels<I: CfgInstruction>(cfg: &mut Cfg<I>) {
+    let targets = cfg
+        .blocks
+        .values()
+        .flat_map(|block| match block.instructions.last().map(|i| i.flow()) {
+            Some(Flow::Jump(target) | Flow::ConditionalJump(target)) => vec![target.to_string()],
+            Some(Flow::MultiJump(targets)) => targets.into_iter().map(str::to_string).collect(),
+            _ => vec![],
+        })
+        .collect::<HashSet<_>>();
+    for block in cfg.blocks.values_mut() {
+        if let Some(Flow::Label(label)) = block.instructions.first().map(|i| i.flow())
+            && !targets.contains(label)
+        {
+            block.instructions.remove(0);
+        }
+    }
+}
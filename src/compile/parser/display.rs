@@ -0,0 +1,337 @@
+//! prints the ast back out as c. after semantic analysis the names are the resolved unique names and
+//! the loop/switch labels are shown as comments, so this is only c-like, not something to recompile
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{
+    BinaryOperator, Block, BlockItem, Declaration, Expression, ForInit, FunctionDefinition,
+    PostfixOperator, Program, Statement, UnaryOperator,
+};
+
+const INDENT: &str = "    ";
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Program::Program(function) = self;
+        write!(f, "{}", function)
+    }
+}
+
+impl Display for FunctionDefinition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let FunctionDefinition::Function { name, body } = self;
+        write!(f, "int {}(void) ", name)?;
+        fmt_block(f, body, 0)?;
+        writeln!(f)
+    }
+}
+
+impl Display for Declaration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Declaration::Declaration { name, init } = self;
+        match init {
+            Some(init) => write!(f, "int {} = {};", name, init),
+            None => write!(f, "int {};", name),
+        }
+    }
+}
+
+fn indent(f: &mut Formatter<'_>, depth: usize) -> fmt::Result {
+    (0..depth).try_for_each(|_| f.write_str(INDENT))
+}
+
+fn fmt_label(f: &mut Formatter<'_>, label: &Option<String>) -> fmt::Result {
+    match label {
+        Some(label) => write!(f, " /* {} */", label),
+        None => Ok(()),
+    }
+}
+
+//block braces open on the current line, the closing brace is at `depth`
+fn fmt_block(f: &mut Formatter<'_>, block: &Block, depth: usize) -> fmt::Result {
+    let Block::Block(items) = block;
+    writeln!(f, "{{")?;
+    items.iter().try_for_each(|item| match item {
+        BlockItem::S(statement) => fmt_statement(f, statement, depth + 1),
+        BlockItem::D(declaration) => {
+            indent(f, depth + 1)?;
+            writeln!(f, "{}", declaration)
+        }
+    })?;
+    indent(f, depth)?;
+    write!(f, "}}")
+}
+
+//bodies of if/loops stay on the same line when they're blocks, otherwise they get their own
+//indented line
+fn fmt_body(f: &mut Formatter<'_>, body: &Statement, depth: usize) -> fmt::Result {
+    match body {
+        Statement::Compound(block) => {
+            write!(f, " ")?;
+            fmt_block(f, block, depth)?;
+            writeln!(f)
+        }
+        statement => {
+            writeln!(f)?;
+            fmt_statement(f, statement, depth + 1)
+        }
+    }
+}
+
+fn fmt_statement(f: &mut Formatter<'_>, statement: &Statement, depth: usize) -> fmt::Result {
+    match statement {
+        //labels are outdented by one so they stand out like they would in hand written code
+        Statement::Label { label, body } => {
+            indent(f, depth.saturating_sub(1))?;
+            writeln!(f, "{}:", label)?;
+            return fmt_statement(f, body, depth);
+        }
+        Statement::Case {
+            condition,
+            body,
+            label,
+        } => {
+            indent(f, depth.saturating_sub(1))?;
+            write!(f, "case {}:", condition)?;
+            fmt_label(f, label)?;
+            writeln!(f)?;
+            return fmt_statement(f, body, depth);
+        }
+        Statement::Default { body, label } => {
+            indent(f, depth.saturating_sub(1))?;
+            write!(f, "default:")?;
+            fmt_label(f, label)?;
+            writeln!(f)?;
+            return fmt_statement(f, body, depth);
+        }
+        _ => indent(f, depth)?,
+    }
+
+    match statement {
+        Statement::Return(expression) => writeln!(f, "return {};", expression),
+        Statement::Expression(expression) => writeln!(f, "{};", expression),
+        Statement::Null => writeln!(f, ";"),
+        Statement::Goto(label) => writeln!(f, "goto {};", label),
+        Statement::Break(label) => {
+            write!(f, "break;")?;
+            fmt_label(f, label)?;
+            writeln!(f)
+        }
+        Statement::Continue(label) => {
+            write!(f, "continue;")?;
+            fmt_label(f, label)?;
+            writeln!(f)
+        }
+        Statement::Compound(block) => {
+            fmt_block(f, block, depth)?;
+            writeln!(f)
+        }
+        Statement::If {
+            condition,
+            then_statement,
+            else_statement,
+        } => {
+            write!(f, "if ({})", condition)?;
+            fmt_body(f, then_statement, depth)?;
+            if let Some(else_statement) = else_statement {
+                indent(f, depth)?;
+                write!(f, "else")?;
+                fmt_body(f, else_statement, depth)?;
+            }
+            Ok(())
+        }
+        Statement::While {
+            condition,
+            body,
+            label,
+        } => {
+            write!(f, "while ({})", condition)?;
+            fmt_label(f, label)?;
+            fmt_body(f, body, depth)
+        }
+        Statement::DoWhile {
+            condition,
+            body,
+            label,
+        } => {
+            write!(f, "do")?;
+            fmt_label(f, label)?;
+            fmt_body(f, body, depth)?;
+            indent(f, depth)?;
+            writeln!(f, "while ({});", condition)
+        }
+        Statement::For {
+            init,
+            condition,
+            post,
+            body,
+            label,
+        } => {
+            match init {
+                ForInit::InitDecl(declaration) => write!(f, "for ({}", declaration)?,
+                ForInit::InitExp(Some(expression)) => write!(f, "for ({};", expression)?,
+                ForInit::InitExp(None) => write!(f, "for (;")?,
+            }
+            if let Some(condition) = condition {
+                write!(f, " {}", condition)?;
+            }
+            write!(f, ";")?;
+            if let Some(post) = post {
+                write!(f, " {}", post)?;
+            }
+            write!(f, ")")?;
+            fmt_label(f, label)?;
+            fmt_body(f, body, depth)
+        }
+        Statement::Switch {
+            condition,
+            body,
+            label,
+            case_expressions,
+            default,
+        } => {
+            write!(f, "switch ({})", condition)?;
+            fmt_label(f, label)?;
+            if !case_expressions.is_empty() || *default {
+                write!(f, " /* cases: {:?}", case_expressions)?;
+                if *default {
+                    write!(f, ", default")?;
+                }
+                write!(f, " */")?;
+            }
+            fmt_body(f, body, depth)
+        }
+        Statement::Label { .. } | Statement::Case { .. } | Statement::Default { .. } => {
+            unreachable!("handled above")
+        }
+    }
+}
+
+// precedence of an expression as a whole, used to decide when a subexpression needs parentheses
+fn precedence(expression: &Expression) -> i64 {
+    match expression {
+        Expression::IntConstant(_) | Expression::Var(_) | Expression::Postfix { .. } => 70,
+        Expression::Unary { .. } => 60,
+        Expression::Binary {
+            binary_operator, ..
+        } => binary_operator.precedence(),
+        Expression::Conditional { .. } => BinaryOperator::Ternary.precedence(),
+        Expression::Assignment { .. } => BinaryOperator::Assigmnent.precedence(),
+    }
+}
+
+fn fmt_operand(f: &mut Formatter<'_>, expression: &Expression, min_precedence: i64) -> fmt::Result {
+    if precedence(expression) < min_precedence {
+        write!(f, "({})", expression)
+    } else {
+        write!(f, "{}", expression)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::IntConstant(val) => write!(f, "{}", val),
+            Expression::Var(name) => write!(f, "{}", name),
+            Expression::Unary {
+                unary_operator,
+                expression,
+            } => {
+                write!(f, "{}", unary_operator)?;
+                //keeps `- -x` from turning into `--x`
+                if let Expression::Unary { .. } = **expression {
+                    write!(f, "({})", expression)
+                } else {
+                    fmt_operand(f, expression, precedence(self))
+                }
+            }
+            Expression::Postfix {
+                postfix_operator,
+                expression,
+            } => {
+                fmt_operand(f, expression, precedence(self))?;
+                write!(f, "{}", postfix_operator)
+            }
+            Expression::Binary {
+                binary_operator,
+                left_expression,
+                right_expression,
+            } => {
+                //left associative, so only the right side needs parens at equal precedence
+                fmt_operand(f, left_expression, binary_operator.precedence())?;
+                write!(f, " {} ", binary_operator)?;
+                fmt_operand(f, right_expression, binary_operator.precedence() + 1)
+            }
+            Expression::Assignment {
+                left_expression,
+                right_expression,
+                operator,
+            } => {
+                fmt_operand(f, left_expression, precedence(self) + 1)?;
+                match operator {
+                    Some(operator) => write!(f, " {}= ", operator)?,
+                    None => write!(f, " = ")?,
+                }
+                fmt_operand(f, right_expression, precedence(self))
+            }
+            Expression::Conditional {
+                condition,
+                true_case,
+                false_case,
+            } => {
+                fmt_operand(f, condition, precedence(self) + 1)?;
+                write!(f, " ? {} : ", true_case)?;
+                fmt_operand(f, false_case, precedence(self))
+            }
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Complement => "~",
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+            UnaryOperator::Increment => "++",
+            UnaryOperator::Decrement => "--",
+        })
+    }
+}
+
+impl Display for PostfixOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PostfixOperator::Increment => "++",
+            PostfixOperator::Decrement => "--",
+        })
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryOperator::Add => f.write_str("+"),
+            BinaryOperator::Subtract => f.write_str("-"),
+            BinaryOperator::Multiply => f.write_str("*"),
+            BinaryOperator::Divide => f.write_str("/"),
+            BinaryOperator::Remainder => f.write_str("%"),
+            BinaryOperator::BitwiseAnd => f.write_str("&"),
+            BinaryOperator::BitwiseOr => f.write_str("|"),
+            BinaryOperator::BitwiseXor => f.write_str("^"),
+            BinaryOperator::LeftShift => f.write_str("<<"),
+            BinaryOperator::RightShift => f.write_str(">>"),
+            BinaryOperator::And => f.write_str("&&"),
+            BinaryOperator::Or => f.write_str("||"),
+            BinaryOperator::Equal => f.write_str("=="),
+            BinaryOperator::NotEqual => f.write_str("!="),
+            BinaryOperator::LessThan => f.write_str("<"),
+            BinaryOperator::Leq => f.write_str("<="),
+            BinaryOperator::GreaterThan => f.write_str(">"),
+            BinaryOperator::Geq => f.write_str(">="),
+            BinaryOperator::Assigmnent => f.write_str("="),
+            BinaryOperator::CompoundAssignment(operator) => write!(f, "{}=", operator),
+            BinaryOperator::Ternary => f.write_str("?"),
+        }
+    }
+}
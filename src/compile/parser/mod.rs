@@ -1,3 +1,4 @@
+mod display;
 mod types;
 
 use itertools::peek_nth;
@@ -119,7 +120,7 @@ fn parse_declaration(iter: &mut TokenStream) -> Result<Declaration> {
     expect!(iter, Token::Semicolon => ())?;
     Ok(Declaration::Declaration {
         name: identifier,
-        init: init,
+        init,
     })
 }
 
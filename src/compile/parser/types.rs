@@ -1,31 +1,37 @@
 use itertools::PeekNth;
+use serde::Serialize;
 
 use super::Lexer;
 
 pub type TokenStream = PeekNth<Lexer>;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Program {
     Program(FunctionDefinition),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum FunctionDefinition {
     Function { name: String, body: Block },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum BlockItem {
     S(Statement),
     D(Declaration),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Block {
     Block(Vec<BlockItem>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Statement {
     Return(Expression),
     Expression(Expression),
@@ -78,13 +84,15 @@ pub enum Statement {
     Null,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum ForInit {
     InitDecl(Declaration),
     InitExp(Option<Expression>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Declaration {
     Declaration {
         name: String,
@@ -92,7 +100,8 @@ pub enum Declaration {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum Expression {
     IntConstant(i32),
     Unary {
@@ -121,13 +130,15 @@ pub enum Expression {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum PostfixOperator {
     Increment,
     Decrement,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum UnaryOperator {
     Complement,
     Negate,
@@ -136,7 +147,8 @@ pub enum UnaryOperator {
     Decrement,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub enum BinaryOperator {
     Add,
     Subtract,
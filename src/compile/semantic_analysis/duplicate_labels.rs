@@ -40,7 +40,7 @@ fn resolve_statement(
         return Ok(());
     };
     if labels.contains(label) {
-        return Err(Error::SemanticError("duplicate label"));
+        Err(Error::SemanticError("duplicate label"))
     } else {
         labels.insert(label.clone());
         Ok(())
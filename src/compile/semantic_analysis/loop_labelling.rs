@@ -25,7 +25,7 @@ fn resolve_function(function: &mut parser::FunctionDefinition) -> Result<()> {
         name,
     } = function;
     body.iter_mut()
-        .try_for_each(|block_item| resolve_block_item(block_item, None, None, Inner::None, &name))
+        .try_for_each(|block_item| resolve_block_item(block_item, None, None, Inner::None, name))
 }
 
 fn resolve_block_item(
@@ -27,8 +27,7 @@ fn resolve_function(function: &parser::FunctionDefinition) -> Result<()> {
         body: parser::Block::Block(body),
         ..
     } = function;
-    body.iter()
-        .try_for_each(|block_item| resolve_block_item(block_item))
+    body.iter().try_for_each(resolve_block_item)
 }
 
 fn resolve_block_item(block_item: &parser::BlockItem) -> Result<()> {
@@ -90,9 +89,9 @@ fn resolve_statement(statement: &parser::Statement) -> Result<()> {
             resolve_statement(body)
         }
 
-        parser::Statement::Compound(parser::Block::Block(body)) => body
-            .iter()
-            .try_for_each(|block_item| resolve_block_item(block_item)),
+        parser::Statement::Compound(parser::Block::Block(body)) => {
+            body.iter().try_for_each(resolve_block_item)
+        }
 
         parser::Statement::Default { body, .. } => resolve_statement(body),
 
@@ -23,7 +23,7 @@ fn resolve_block(block: &mut parser::Block) -> Result<Vec<CaseType>> {
     let parser::Block::Block(block_items) = block;
     Ok(block_items
         .iter_mut()
-        .map(|block_item| resolve_block_item(block_item))
+        .map(resolve_block_item)
         .collect::<Result<Vec<Vec<CaseType>>>>()?
         .into_iter()
         .flatten()
@@ -91,7 +91,7 @@ fn resolve_statement(statement: &mut parser::Statement) -> Result<Vec<CaseType>>
         } => {
             let mut res = resolve_statement(then_statement)?;
             if let Some(es) = else_statement {
-                res.extend(resolve_statement(es)?.into_iter());
+                res.extend(resolve_statement(es)?);
             }
             Ok(res)
         }
@@ -59,7 +59,7 @@ fn resolve_declaration(
 ) -> Result<()> {
     let parser::Declaration::Declaration { name, init } = declaration;
     if let Some(variable) = variable_map.get(name)
-        && variable.from_current_block == true
+        && variable.from_current_block
     {
         Err(Error::SemanticError("duplicate declaration"))
     } else {
@@ -93,9 +93,9 @@ fn resolve_statement(
             else_statement,
         } => {
             resolve_expression(condition, variable_map)?;
-            resolve_statement(&mut **then_statement, variable_map)?;
+            resolve_statement(then_statement, variable_map)?;
             if let Some(statement) = else_statement {
-                resolve_statement(&mut **statement, variable_map)?;
+                resolve_statement(statement, variable_map)?;
             }
             Ok(())
         }
@@ -207,8 +207,8 @@ fn resolve_expression(
 }
 
 fn make_temporary_name(name: &str) -> String {
-    let temp_name = format!("tmp.{}.{:?}", name, TACKY_COUNTER);
-    TACKY_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let temp_name = format!("tmp.{}.{}", name, TACKY_COUNTER.get());
+    TACKY_COUNTER.set(TACKY_COUNTER.get() + 1);
     temp_name
 }
 
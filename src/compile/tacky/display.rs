@@ -0,0 +1,105 @@
+//! three address listing of tacky, one instruction per line
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{BinaryOperator, FunctionDefinition, Instruction, Program, UnaryOperator, Value};
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Program::Program(function) = self;
+        write!(f, "{}", function)
+    }
+}
+
+impl Display for FunctionDefinition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let FunctionDefinition::Function { identifier, body } = self;
+        writeln!(f, "function {} {{", identifier)?;
+        body.iter()
+            .try_for_each(|instruction| writeln!(f, "{}", instruction))?;
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Label(label) => write!(f, "{}:", label),
+            Instruction::Return(value) => write!(f, "    return {}", value),
+            Instruction::UnaryOperator {
+                unary_operator,
+                src,
+                dst,
+            } => write!(f, "    {} = {} {}", dst, unary_operator, src),
+            Instruction::BinaryOperator {
+                binary_operator,
+                src1,
+                src2,
+                dst,
+            } => write!(f, "    {} = {} {} {}", dst, src1, binary_operator, src2),
+            Instruction::Copy { src, dst } => write!(f, "    {} = {}", dst, src),
+            Instruction::Jump(target) => write!(f, "    jump {}", target),
+            Instruction::JumpIfZero { target, condition } => {
+                write!(f, "    jz {}, {}", condition, target)
+            }
+            Instruction::JumpIfNotZero { target, condition } => {
+                write!(f, "    jnz {}, {}", condition, target)
+            }
+            Instruction::JumpTable {
+                index,
+                targets,
+                default,
+            } => write!(
+                f,
+                "    jumptable {}, {}, {}",
+                index,
+                targets.join(" "),
+                default
+            ),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Constant(val) => write!(f, "{}", val),
+            Value::Var(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Complement => "~",
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+            UnaryOperator::Increment => "++",
+            UnaryOperator::Decrement => "--",
+        })
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::BitwiseAnd => "&",
+            BinaryOperator::BitwiseOr => "|",
+            BinaryOperator::BitwiseXor => "^",
+            BinaryOperator::LeftShift => "<<",
+            BinaryOperator::RightShift => ">>",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::Leq => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::Geq => ">=",
+        })
+    }
+}
@@ -0,0 +1,183 @@
+//! runs tacky directly, as a reference for what the compiled program should do
+//!
+//! arithmetic mirrors the generated code rather than the c standard: adds and multiplies wrap, and
+//! shift counts are masked to 5 bits like `sall %cl` does. the things that would trap or read
+//! garbage natively (dividing by zero, `INT_MIN / -1`, reading a variable that was never written)
+//! stop the interpreter with an error instead
+
+use std::collections::HashMap;
+
+use super::{BinaryOperator, FunctionDefinition, Instruction, Program, UnaryOperator, Value};
+use crate::error::{Error, Result};
+
+struct Function<'a> {
+    body: &'a [Instruction],
+    labels: HashMap<&'a str, usize>,
+}
+
+/// one activation of a function: where it is, and the storage for its variables
+struct Frame<'a> {
+    function: &'a Function<'a>,
+    pc: usize,
+    memory: HashMap<&'a str, i32>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(function: &'a Function<'a>) -> Self {
+        Frame {
+            function,
+            pc: 0,
+            memory: HashMap::new(),
+        }
+    }
+
+    fn load(&self, value: &Value) -> Result<i32> {
+        match value {
+            Value::Constant(val) => Ok(*val),
+            Value::Var(name) => self.memory.get(name.as_str()).copied().ok_or_else(|| {
+                Error::InterpreterError(format!("read of uninitialised variable {}", name))
+            }),
+        }
+    }
+
+    fn store(&mut self, dst: &'a Value, val: i32) {
+        let Value::Var(name) = dst else {
+            unreachable!("tacky never writes to a constant")
+        };
+        self.memory.insert(name, val);
+    }
+
+    fn jump(&mut self, target: &str) -> Result<()> {
+        self.pc =
+            *self.function.labels.get(target).ok_or_else(|| {
+                Error::InterpreterError(format!("jump to missing label {}", target))
+            })?;
+        Ok(())
+    }
+}
+
+/// runs `main` and returns what it returned
+pub(crate) fn interpret(program: &Program) -> Result<i32> {
+    let Program::Program(function) = program;
+    let FunctionDefinition::Function { identifier, body } = function;
+    let functions = HashMap::from([(
+        identifier.as_str(),
+        Function {
+            body,
+            labels: body
+                .iter()
+                .enumerate()
+                .filter_map(|(i, instruction)| match instruction {
+                    Instruction::Label(label) => Some((label.as_str(), i)),
+                    _ => None,
+                })
+                .collect(),
+        },
+    )]);
+
+    let main = functions
+        .get("main")
+        .ok_or_else(|| Error::InterpreterError("no main function".to_string()))?;
+    let mut call_stack = vec![Frame::new(main)];
+
+    while let Some(frame) = call_stack.last_mut() {
+        let Some(instruction) = frame.function.body.get(frame.pc) else {
+            return Err(Error::InterpreterError(
+                "fell off the end of a function".to_string(),
+            ));
+        };
+        frame.pc += 1;
+        match instruction {
+            Instruction::Return(value) => {
+                let val = frame.load(value)?;
+                call_stack.pop();
+                if call_stack.is_empty() {
+                    return Ok(val);
+                }
+            }
+            Instruction::UnaryOperator {
+                unary_operator,
+                src,
+                dst,
+            } => {
+                let val = unary(unary_operator, frame.load(src)?);
+                frame.store(dst, val);
+            }
+            Instruction::BinaryOperator {
+                binary_operator,
+                src1,
+                src2,
+                dst,
+            } => {
+                let val = binary(binary_operator, frame.load(src1)?, frame.load(src2)?)?;
+                frame.store(dst, val);
+            }
+            Instruction::Copy { src, dst } => {
+                let val = frame.load(src)?;
+                frame.store(dst, val);
+            }
+            Instruction::Jump(target) => frame.jump(target)?,
+            Instruction::JumpIfZero { target, condition } => {
+                if frame.load(condition)? == 0 {
+                    frame.jump(target)?;
+                }
+            }
+            Instruction::JumpIfNotZero { target, condition } => {
+                if frame.load(condition)? != 0 {
+                    frame.jump(target)?;
+                }
+            }
+            Instruction::JumpTable {
+                index,
+                targets,
+                default,
+            } => {
+                let target = usize::try_from(frame.load(index)?)
+                    .ok()
+                    .and_then(|index| targets.get(index))
+                    .unwrap_or(default);
+                frame.jump(target)?;
+            }
+            Instruction::Label(_) => (),
+        }
+    }
+    unreachable!("returning from the last frame exits the loop")
+}
+
+fn unary(unary_operator: &UnaryOperator, val: i32) -> i32 {
+    match unary_operator {
+        UnaryOperator::Complement => !val,
+        UnaryOperator::Negate => val.wrapping_neg(),
+        UnaryOperator::Not => (val == 0) as i32,
+        UnaryOperator::Increment => val.wrapping_add(1),
+        UnaryOperator::Decrement => val.wrapping_sub(1),
+    }
+}
+
+fn binary(binary_operator: &BinaryOperator, left: i32, right: i32) -> Result<i32> {
+    Ok(match binary_operator {
+        BinaryOperator::Add => left.wrapping_add(right),
+        BinaryOperator::Subtract => left.wrapping_sub(right),
+        BinaryOperator::Multiply => left.wrapping_mul(right),
+        BinaryOperator::Divide | BinaryOperator::Remainder if right == 0 => {
+            return Err(Error::InterpreterError("division by zero".to_string()));
+        }
+        BinaryOperator::Divide => left.checked_div(right).ok_or_else(overflow)?,
+        BinaryOperator::Remainder => left.checked_rem(right).ok_or_else(overflow)?,
+        BinaryOperator::BitwiseAnd => left & right,
+        BinaryOperator::BitwiseOr => left | right,
+        BinaryOperator::BitwiseXor => left ^ right,
+        BinaryOperator::LeftShift => left.wrapping_shl(right as u32),
+        BinaryOperator::RightShift => left.wrapping_shr(right as u32),
+        BinaryOperator::Equal => (left == right) as i32,
+        BinaryOperator::NotEqual => (left != right) as i32,
+        BinaryOperator::LessThan => (left < right) as i32,
+        BinaryOperator::Leq => (left <= right) as i32,
+        BinaryOperator::GreaterThan => (left > right) as i32,
+        BinaryOperator::Geq => (left >= right) as i32,
+    })
+}
+
+fn overflow() -> Error {
+    Error::InterpreterError("INT_MIN divided by -1".to_string())
+}
@@ -1,7 +1,17 @@
+mod display;
+mod interpreter;
+mod switch_lowering;
+mod text;
+
+use super::cfg::{CfgInstruction, Flow};
 use super::parser;
 use crate::error::Result;
-use std::sync::atomic::AtomicI64;
-use std::sync::atomic::Ordering::Relaxed;
+use serde::Serialize;
+use std::cell::Cell;
+use switch_lowering::lower_switch_dispatch;
+
+pub(super) use interpreter::interpret;
+pub(super) use text::parse_tacky;
 
 macro_rules! continue_format_string {
     () => {
@@ -27,12 +37,14 @@ macro_rules! default_format_string {
     };
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Program {
     Program(FunctionDefinition),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum FunctionDefinition {
     Function {
         identifier: String,
@@ -40,7 +52,8 @@ pub(super) enum FunctionDefinition {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Instruction {
     Return(Value),
     UnaryOperator {
@@ -67,16 +80,24 @@ pub(super) enum Instruction {
         target: String,
         condition: Value,
     },
+    /// jumps to `targets[index]`, or to `default` when index is out of range
+    JumpTable {
+        index: Value,
+        targets: Vec<String>,
+        default: String,
+    },
     Label(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum Value {
     Constant(i32),
     Var(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum UnaryOperator {
     Complement,
     Negate,
@@ -85,7 +106,8 @@ pub(super) enum UnaryOperator {
     Decrement,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", content = "value")]
 pub(super) enum BinaryOperator {
     Add,
     Subtract,
@@ -105,7 +127,129 @@ pub(super) enum BinaryOperator {
     Geq,
 }
 
-pub(super) static TACKY_COUNTER: AtomicI64 = AtomicI64::new(0);
+impl Instruction {
+    /// the variable the instruction writes, if any
+    pub(super) fn destination(&self) -> Option<&Value> {
+        match self {
+            Instruction::Copy { dst, .. }
+            | Instruction::UnaryOperator { dst, .. }
+            | Instruction::BinaryOperator { dst, .. } => Some(dst),
+            Instruction::Return(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpIfZero { .. }
+            | Instruction::JumpIfNotZero { .. }
+            | Instruction::JumpTable { .. }
+            | Instruction::Label(_) => None,
+        }
+    }
+
+    pub(super) fn destination_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            Instruction::Copy { dst, .. }
+            | Instruction::UnaryOperator { dst, .. }
+            | Instruction::BinaryOperator { dst, .. } => Some(dst),
+            Instruction::Return(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpIfZero { .. }
+            | Instruction::JumpIfNotZero { .. }
+            | Instruction::JumpTable { .. }
+            | Instruction::Label(_) => None,
+        }
+    }
+
+    /// the values the instruction reads
+    pub(super) fn sources(&self) -> Vec<&Value> {
+        match self {
+            Instruction::Return(value)
+            | Instruction::Copy { src: value, .. }
+            | Instruction::UnaryOperator { src: value, .. }
+            | Instruction::JumpIfZero {
+                condition: value, ..
+            }
+            | Instruction::JumpIfNotZero {
+                condition: value, ..
+            }
+            | Instruction::JumpTable { index: value, .. } => vec![value],
+            Instruction::BinaryOperator { src1, src2, .. } => vec![src1, src2],
+            Instruction::Jump(_) | Instruction::Label(_) => vec![],
+        }
+    }
+
+    pub(super) fn sources_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Instruction::Return(value)
+            | Instruction::Copy { src: value, .. }
+            | Instruction::UnaryOperator { src: value, .. }
+            | Instruction::JumpIfZero {
+                condition: value, ..
+            }
+            | Instruction::JumpIfNotZero {
+                condition: value, ..
+            }
+            | Instruction::JumpTable { index: value, .. } => vec![value],
+            Instruction::BinaryOperator { src1, src2, .. } => vec![src1, src2],
+            Instruction::Jump(_) | Instruction::Label(_) => vec![],
+        }
+    }
+
+    /// points a jump at `to` wherever it went to `from`, returning whether it did
+    pub(super) fn retarget(&mut self, from: &str, to: &str) -> bool {
+        match self {
+            Instruction::Jump(target)
+            | Instruction::JumpIfZero { target, .. }
+            | Instruction::JumpIfNotZero { target, .. }
+                if target == from =>
+            {
+                *target = to.to_string();
+                true
+            }
+            Instruction::JumpTable {
+                targets, default, ..
+            } => {
+                let mut retargeted = false;
+                for target in targets.iter_mut().chain(std::iter::once(default)) {
+                    if target == from {
+                        *target = to.to_string();
+                        retargeted = true;
+                    }
+                }
+                retargeted
+            }
+            _ => false,
+        }
+    }
+}
+
+impl CfgInstruction for Instruction {
+    fn flow(&self) -> Flow<'_> {
+        match self {
+            Instruction::Label(label) => Flow::Label(label),
+            Instruction::Jump(target) => Flow::Jump(target),
+            Instruction::JumpIfZero { target, .. } | Instruction::JumpIfNotZero { target, .. } => {
+                Flow::ConditionalJump(target)
+            }
+            Instruction::JumpTable {
+                targets, default, ..
+            } => Flow::MultiJump(
+                targets
+                    .iter()
+                    .chain([default])
+                    .map(String::as_str)
+                    .collect(),
+            ),
+            Instruction::Return(_) => Flow::Return,
+            Instruction::UnaryOperator { .. }
+            | Instruction::BinaryOperator { .. }
+            | Instruction::Copy { .. } => Flow::Straight,
+        }
+    }
+}
+
+thread_local! {
+    // per thread so that translation units compiled in parallel get the same names they would
+    // get when compiled alone
+    pub(super) static TACKY_COUNTER: Cell<i64> = const { Cell::new(0) };
+}
 
 pub(super) fn tacky_gen(ast: parser::Program) -> Result<Program> {
     Ok(parse_program(ast))
@@ -170,12 +314,14 @@ fn parse_statement(
             then_statement,
             else_statement,
         } => {
-            let cond = parse_expression_to_tacky(function_name, condition, instructions);
             let else_label = make_temp_label(function_name);
-            instructions.push(Instruction::JumpIfZero {
-                target: else_label.clone(),
-                condition: cond,
-            });
+            parse_condition(
+                function_name,
+                condition,
+                false,
+                else_label.clone(),
+                instructions,
+            );
             parse_statement(function_name, *then_statement, instructions);
             if let Some(statement) = else_statement {
                 let end_label = make_temp_label(function_name);
@@ -220,11 +366,13 @@ fn parse_statement(
             let continue_label = format!(continue_format_string!(), label);
             let break_label = format!(break_format_string!(), label);
             instructions.push(Instruction::Label(continue_label.clone()));
-            let val = parse_expression_to_tacky(function_name, condition, instructions);
-            instructions.push(Instruction::JumpIfZero {
-                target: break_label.clone(),
-                condition: val,
-            });
+            parse_condition(
+                function_name,
+                condition,
+                false,
+                break_label.clone(),
+                instructions,
+            );
             parse_statement(function_name, *body, instructions);
             instructions.push(Instruction::Jump(continue_label));
             instructions.push(Instruction::Label(break_label));
@@ -242,11 +390,7 @@ fn parse_statement(
             instructions.push(Instruction::Label(label.clone()));
             parse_statement(function_name, *body, instructions);
             instructions.push(Instruction::Label(continue_label));
-            let val = parse_expression_to_tacky(function_name, condition, instructions);
-            instructions.push(Instruction::JumpIfNotZero {
-                target: label,
-                condition: val,
-            });
+            parse_condition(function_name, condition, true, label, instructions);
             instructions.push(Instruction::Label(break_label));
         }
         parser::Statement::For {
@@ -265,11 +409,13 @@ fn parse_statement(
             parse_for_init(function_name, init, instructions);
             instructions.push(Instruction::Label(label.clone()));
             if let Some(expression) = condition {
-                let val = parse_expression_to_tacky(function_name, expression, instructions);
-                instructions.push(Instruction::JumpIfZero {
-                    target: break_label.clone(),
-                    condition: val,
-                });
+                parse_condition(
+                    function_name,
+                    expression,
+                    false,
+                    break_label.clone(),
+                    instructions,
+                );
             }
             parse_statement(function_name, *body, instructions);
             instructions.push(Instruction::Label(continue_label));
@@ -293,27 +439,16 @@ fn parse_statement(
             let break_label = format!(break_format_string!(), label);
             let v = parse_expression_to_tacky(function_name, condition, instructions);
 
-            case_expressions.into_iter().for_each(|val| {
-                let dest = make_temp_label(function_name);
-                instructions.push(Instruction::BinaryOperator {
-                    binary_operator: BinaryOperator::Equal,
-                    src1: v.clone(),
-                    src2: Value::Constant(val),
-                    dst: Value::Var(dest.clone()),
-                });
-                instructions.push(Instruction::JumpIfNotZero {
-                    target: case_format_string!(function_name, label.clone(), val),
-                    condition: Value::Var(dest),
-                });
-            });
-
-            if default {
-                instructions.push(Instruction::Jump(default_format_string!(
-                    function_name,
-                    label
-                )));
-            }
-            instructions.push(Instruction::Jump(break_label.clone()));
+            let cases = case_expressions
+                .into_iter()
+                .map(|val| (val, case_format_string!(function_name, label.clone(), val)))
+                .collect();
+            let fallback = if default {
+                default_format_string!(function_name, label)
+            } else {
+                break_label.clone()
+            };
+            lower_switch_dispatch(function_name, v, cases, fallback, instructions);
             parse_statement(function_name, *body, instructions);
             instructions.push(Instruction::Label(break_label));
         }
@@ -395,11 +530,11 @@ fn parse_expression_to_tacky(
             unary_operator,
             expression,
         } => {
-            let src = parse_expression_to_tacky(&function_name, *expression, instructions);
+            let src = parse_expression_to_tacky(function_name, *expression, instructions);
             let dst = Value::Var(make_temp_identifier(function_name));
             instructions.push(Instruction::UnaryOperator {
                 unary_operator: parse_unary_operator(unary_operator),
-                src: src,
+                src,
                 dst: dst.clone(),
             });
             dst
@@ -409,16 +544,15 @@ fn parse_expression_to_tacky(
             left_expression,
             right_expression,
         } => {
-            let left_val =
-                parse_expression_to_tacky(&function_name, *left_expression, instructions);
-            let temp_label = make_temp_label(&function_name);
-            let dst = Value::Var(make_temp_identifier(&function_name));
+            let left_val = parse_expression_to_tacky(function_name, *left_expression, instructions);
+            let temp_label = make_temp_label(function_name);
+            let dst = Value::Var(make_temp_identifier(function_name));
             instructions.push(Instruction::JumpIfZero {
                 target: temp_label.clone(),
                 condition: left_val,
             });
             let right_val =
-                parse_expression_to_tacky(&function_name, *right_expression, instructions);
+                parse_expression_to_tacky(function_name, *right_expression, instructions);
             instructions.push(Instruction::JumpIfZero {
                 target: temp_label.clone(),
                 condition: right_val,
@@ -443,16 +577,15 @@ fn parse_expression_to_tacky(
             left_expression,
             right_expression,
         } => {
-            let left_val =
-                parse_expression_to_tacky(&function_name, *left_expression, instructions);
-            let temp_label = make_temp_label(&function_name);
-            let dst = Value::Var(make_temp_identifier(&function_name));
+            let left_val = parse_expression_to_tacky(function_name, *left_expression, instructions);
+            let temp_label = make_temp_label(function_name);
+            let dst = Value::Var(make_temp_identifier(function_name));
             instructions.push(Instruction::JumpIfNotZero {
                 target: temp_label.clone(),
                 condition: left_val,
             });
             let right_val =
-                parse_expression_to_tacky(&function_name, *right_expression, instructions);
+                parse_expression_to_tacky(function_name, *right_expression, instructions);
             instructions.push(Instruction::JumpIfNotZero {
                 target: temp_label.clone(),
                 condition: right_val,
@@ -477,10 +610,9 @@ fn parse_expression_to_tacky(
             left_expression,
             right_expression,
         } => {
-            let left_val =
-                parse_expression_to_tacky(&function_name, *left_expression, instructions);
+            let left_val = parse_expression_to_tacky(function_name, *left_expression, instructions);
             let right_val =
-                parse_expression_to_tacky(&function_name, *right_expression, instructions);
+                parse_expression_to_tacky(function_name, *right_expression, instructions);
             let dst = Value::Var(make_temp_identifier(function_name));
             instructions.push(Instruction::BinaryOperator {
                 binary_operator: parse_binary_operator(binary_operator),
@@ -507,7 +639,7 @@ fn parse_expression_to_tacky(
                     });
                 } else {
                     instructions.push(Instruction::Copy {
-                        src: src,
+                        src,
                         dst: dst.clone(),
                     });
                 }
@@ -540,13 +672,15 @@ fn parse_expression_to_tacky(
             true_case,
             false_case,
         } => {
-            let c = parse_expression_to_tacky(function_name, *condition, instructions);
             let end = make_temp_label(function_name);
             let e2_label = make_temp_label(function_name);
-            instructions.push(Instruction::JumpIfZero {
-                target: e2_label.clone(),
-                condition: c,
-            });
+            parse_condition(
+                function_name,
+                *condition,
+                false,
+                e2_label.clone(),
+                instructions,
+            );
             let v1 = parse_expression_to_tacky(function_name, *true_case, instructions);
             let result = Value::Var(make_temp_identifier(function_name));
             instructions.push(Instruction::Copy {
@@ -566,6 +700,73 @@ fn parse_expression_to_tacky(
     }
 }
 
+/// jumps to `target` if the condition's truth is `jump_if` and falls through otherwise. `&&`, `||`
+/// and `!` turn into jumps directly instead of working out a 0 or 1 to test
+fn parse_condition(
+    function_name: &str,
+    condition: parser::Expression,
+    jump_if: bool,
+    target: String,
+    instructions: &mut Vec<Instruction>,
+) {
+    match condition {
+        parser::Expression::Binary {
+            binary_operator:
+                binary_operator @ (parser::BinaryOperator::And | parser::BinaryOperator::Or),
+            left_expression,
+            right_expression,
+        } => {
+            //whether the left side alone settles it: false for &&, true for ||
+            let settles = matches!(binary_operator, parser::BinaryOperator::Or);
+            if settles == jump_if {
+                parse_condition(
+                    function_name,
+                    *left_expression,
+                    jump_if,
+                    target.clone(),
+                    instructions,
+                );
+                parse_condition(
+                    function_name,
+                    *right_expression,
+                    jump_if,
+                    target,
+                    instructions,
+                );
+            } else {
+                let skip = make_temp_label(function_name);
+                parse_condition(
+                    function_name,
+                    *left_expression,
+                    settles,
+                    skip.clone(),
+                    instructions,
+                );
+                parse_condition(
+                    function_name,
+                    *right_expression,
+                    jump_if,
+                    target,
+                    instructions,
+                );
+                instructions.push(Instruction::Label(skip));
+            }
+        }
+        parser::Expression::Unary {
+            unary_operator: parser::UnaryOperator::Not,
+            expression,
+        } => parse_condition(function_name, *expression, !jump_if, target, instructions),
+        condition => {
+            let condition = parse_expression_to_tacky(function_name, condition, instructions);
+            instructions.push(if jump_if {
+                Instruction::JumpIfNotZero { target, condition }
+            } else {
+                Instruction::JumpIfZero { target, condition }
+            });
+        }
+    }
+}
+
 fn parse_declaration(
     function_name: &str,
     declaration: parser::Declaration,
@@ -626,14 +827,14 @@ fn parse_binary_operator(binary_operator: parser::BinaryOperator) -> BinaryOpera
     }
 }
 
-fn make_temp_identifier(function_name: &str) -> String {
-    let temp_name = format!("{}-tmp.{:?}", function_name, TACKY_COUNTER);
-    TACKY_COUNTER.fetch_add(1, Relaxed);
+pub(super) fn make_temp_identifier(function_name: &str) -> String {
+    let temp_name = format!("{}-tmp.{}", function_name, TACKY_COUNTER.get());
+    TACKY_COUNTER.set(TACKY_COUNTER.get() + 1);
     temp_name
 }
 
 pub(super) fn make_temp_label(function_name: &str) -> String {
-    let temp_name = format!("{}_tmp_label.{:?}", function_name, TACKY_COUNTER);
-    TACKY_COUNTER.fetch_add(1, Relaxed);
+    let temp_name = format!("{}_tmp_label.{}", function_name, TACKY_COUNTER.get());
+    TACKY_COUNTER.set(TACKY_COUNTER.get() + 1);
     temp_name
 }
@@ -0,0 +1,236 @@
+//! picks how a switch finds the case to jump to
+//!
+//! case values close enough together go through a jump table, indexed by the controlling value
+//! minus the smallest case. anything sparser does a binary search over the sorted case values,
+//! which costs log2(cases) compares instead of one per case
+
+use super::{BinaryOperator, Instruction, Value, make_temp_identifier, make_temp_label};
+
+/// fewer cases than this aren't worth a table
+const MIN_TABLE_CASES: usize = 4;
+/// a table can have at most this many entries per case, the rest being holes that go to the
+/// default
+const MAX_TABLE_ENTRIES_PER_CASE: i64 = 3;
+/// ranges this small are searched one compare at a time
+const MAX_LINEAR_CASES: usize = 3;
+
+/// jumps to the label for `value` out of `cases`, or to `fallback` when no case matches
+pub(super) fn lower_switch_dispatch(
+    function_name: &str,
+    value: Value,
+    mut cases: Vec<(i32, String)>,
+    fallback: String,
+    instructions: &mut Vec<Instruction>,
+) {
+    cases.sort_by_key(|(val, _)| *val);
+    let (Some((min, _)), Some((max, _))) = (cases.first(), cases.last()) else {
+        instructions.push(Instruction::Jump(fallback));
+        return;
+    };
+    let entries = *max as i64 - *min as i64 + 1;
+    if cases.len() >= MIN_TABLE_CASES && entries <= MAX_TABLE_ENTRIES_PER_CASE * cases.len() as i64
+    {
+        jump_table(function_name, value, &cases, fallback, instructions);
+    } else {
+        binary_search(function_name, &value, &cases, &fallback, instructions);
+    }
+}
+
+fn jump_table(
+    function_name: &str,
+    value: Value,
+    cases: &[(i32, String)],
+    fallback: String,
+    instructions: &mut Vec<Instruction>,
+) {
+    let min = cases[0].0;
+    let index = if min == 0 {
+        value
+    } else {
+        let index = Value::Var(make_temp_identifier(function_name));
+        instructions.push(Instruction::BinaryOperator {
+            binary_operator: BinaryOperator::Subtract,
+            src1: value,
+            src2: Value::Constant(min),
+            dst: index.clone(),
+        });
+        index
+    };
+    let mut cases = cases.iter().peekable();
+    let targets = (min as i64..=cases.clone().last().expect("cases aren't empty").0 as i64)
+        .map(|val| match cases.next_if(|(case, _)| *case as i64 == val) {
+            Some((_, label)) => label.clone(),
+            None => fallback.clone(),
+        })
+        .collect();
+    instructions.push(Instruction::JumpTable {
+        index,
+        targets,
+        default: fallback,
+    });
+}
+
+fn binary_search(
+    function_name: &str,
+    value: &Value,
+    cases: &[(i32, String)],
+    fallback: &str,
+    instructions: &mut Vec<Instruction>,
+) {
+    let mut compare = |binary_operator, val: i32, target: String| {
+        let result = Value::Var(make_temp_identifier(function_name));
+        instructions.push(Instruction::BinaryOperator {
+            binary_operator,
+            src1: value.clone(),
+            src2: Value::Constant(val),
+            dst: result.clone(),
+        });
+        instructions.push(Instruction::JumpIfNotZero {
+            target,
+            condition: result,
+        });
+    };
+
+    if cases.len() <= MAX_LINEAR_CASES {
+        cases
+            .iter()
+            .for_each(|(val, label)| compare(BinaryOperator::Equal, *val, label.clone()));
+        instructions.push(Instruction::Jump(fallback.to_string()));
+        return;
+    }
+
+    let middle = cases.len() / 2;
+    let (val, label) = &cases[middle];
+    let lower = make_temp_label(function_name);
+    compare(BinaryOperator::Equal, *val, label.clone());
+    compare(BinaryOperator::LessThan, *val, lower.clone());
+    binary_search(
+        function_name,
+        value,
+        &cases[middle + 1..],
+        fallback,
+        instructions,
+    );
+    instructions.push(Instruction::Label(lower));
+    binary_search(
+        function_name,
+        value,
+        &cases[..middle],
+        fallback,
+        instructions,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::sink;
+
+    use super::{MAX_TABLE_ENTRIES_PER_CASE, MIN_TABLE_CASES, lower_switch_dispatch};
+    use crate::compile::tacky::{BinaryOperator, Instruction, TACKY_COUNTER, Value};
+    use crate::compile::{OptLevel, Optimizations, run_tacky};
+
+    fn lower(values: &[i32]) -> Vec<Instruction> {
+        TACKY_COUNTER.set(0);
+        let cases = values
+            .iter()
+            .map(|val| (*val, format!("case.{}", val)))
+            .collect();
+        let mut instructions = Vec::new();
+        lower_switch_dispatch(
+            "f",
+            Value::Var("x".to_string()),
+            cases,
+            "default".to_string(),
+            &mut instructions,
+        );
+        instructions
+    }
+
+    fn is_table(instructions: &[Instruction]) -> bool {
+        instructions
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::JumpTable { .. }))
+    }
+
+    #[test]
+    fn dense_cases_get_a_table_indexed_from_the_smallest() {
+        let label = |val: i32| format!("case.{}", val);
+        assert_eq!(
+            lower(&[14, 10, 11, 12, 15]),
+            vec![
+                Instruction::BinaryOperator {
+                    binary_operator: BinaryOperator::Subtract,
+                    src1: Value::Var("x".to_string()),
+                    src2: Value::Constant(10),
+                    dst: Value::Var("f-tmp.0".to_string()),
+                },
+                Instruction::JumpTable {
+                    index: Value::Var("f-tmp.0".to_string()),
+                    targets: vec![
+                        label(10),
+                        label(11),
+                        label(12),
+                        "default".to_string(),
+                        label(14),
+                        label(15),
+                    ],
+                    default: "default".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sparse_or_few_cases_are_searched() {
+        let cases = MIN_TABLE_CASES as i32;
+        let widest = MAX_TABLE_ENTRIES_PER_CASE as i32 * cases;
+        //spread out as far as a table allows, then one further
+        assert!(is_table(&lower(&[0, 1, 2, widest - 1])));
+        assert!(!is_table(&lower(&[0, 1, 2, widest])));
+        assert!(!is_table(&lower(&(0..cases - 1).collect::<Vec<_>>())));
+
+        let instructions = lower(&[0, 100, 200, 300, 400, 500, 600]);
+        let compares = |operator| {
+            instructions
+                .iter()
+                .filter(|instruction| {
+                    matches!(instruction, Instruction::BinaryOperator { binary_operator, .. }
+                        if *binary_operator == operator)
+                })
+                .count()
+        };
+        //one equality test per case, and a single less than splitting them into halves short
+        //enough to test one by one
+        assert!(!is_table(&instructions));
+        assert_eq!(compares(BinaryOperator::Equal), 7);
+        assert_eq!(compares(BinaryOperator::LessThan), 1);
+    }
+
+    /// `x - min` wraps for values far below the cases, which has to land outside the table
+    #[test]
+    fn tables_near_int_max_wrap_their_index() {
+        let source = "int main(void) {
+            int total = 0;
+            for (int i = 0; i < 6; i = i + 1) {
+                int x = i == 0 ? 2147483647 : i == 1 ? 2147483645 : i == 2 ? -2147483647 - 1
+                    : i == 3 ? -2147483647 : i == 4 ? 0 : -1;
+                switch (x) {
+                    case 2147483644: total = total + 1; break;
+                    case 2147483645: total = total + 2; break;
+                    case 2147483646: total = total + 4; break;
+                    case 2147483647: total = total + 8; break;
+                    default: total = total + 16;
+                }
+            }
+            return total;
+        }";
+        for level in [OptLevel::O0, OptLevel::O2] {
+            let optimizations = Optimizations {
+                passes: level.passes(),
+                print_after: vec![],
+            };
+            let result = run_tacky(source.to_string(), false, &optimizations, &mut sink());
+            assert_eq!(result.expect("runs"), 8 + 2 + 16 * 4, "{:?}", level);
+        }
+    }
+}
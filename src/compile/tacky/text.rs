@@ -0,0 +1,366 @@
+//! reads back the listing that `Display` prints, so backend tests can be written directly in tacky
+//!
+//! ```text
+//! # comments run to the end of the line
+//! function main {
+//!     x = 5               copy
+//!     t.1 = - x           unary:  ~ - ! ++ --
+//!     t.2 = x + t.1       binary: + - * / % & | ^ << >> == != < <= > >=
+//!     jz t.2, end         also jnz, and `jump end`
+//!     jumptable x, a b c, end
+//! end:
+//!     return t.2
+//! }
+//! ```
+//!
+//! tokens are separated by whitespace, so `- 5` negates the constant 5 while `-5` is the constant
+//! itself
+
+use super::{
+    BinaryOperator, FunctionDefinition, Instruction, Program, TACKY_COUNTER, UnaryOperator, Value,
+};
+use crate::error::{Error, Result};
+
+pub(crate) fn parse_tacky(text: &str) -> Result<Program> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let (line_number, header) = lines.next().ok_or(Error::TackyParseError {
+        line: 0,
+        message: "expected a function, found end of file".to_string(),
+    })?;
+    let identifier = match header.split_whitespace().collect::<Vec<_>>()[..] {
+        ["function", name, "{"] => parse_identifier(line_number, name)?,
+        _ => return Err(error(line_number, "expected `function <name> {`")),
+    };
+
+    let mut body = Vec::new();
+    let mut closed = false;
+    for (line_number, line) in lines.by_ref() {
+        if line == "}" {
+            closed = true;
+            break;
+        }
+        body.push(parse_instruction(line_number, line)?);
+    }
+    if !closed {
+        return Err(error(
+            text.lines().count(),
+            "expected `}` closing the function",
+        ));
+    }
+    if let Some((line_number, _)) = lines.next() {
+        return Err(error(
+            line_number,
+            "expected end of file after the function",
+        ));
+    }
+
+    let program = Program::Program(FunctionDefinition::Function { identifier, body });
+    bump_counter(&program);
+    Ok(program)
+}
+
+fn error(line: usize, message: &str) -> Error {
+    Error::TackyParseError {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn parse_instruction(line_number: usize, line: &str) -> Result<Instruction> {
+    if let Some(label) = line.strip_suffix(':') {
+        return Ok(Instruction::Label(parse_identifier(line_number, label)?));
+    }
+
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    Ok(match tokens[..] {
+        ["return", value] => Instruction::Return(parse_value(line_number, value)?),
+        ["jump", target] => Instruction::Jump(parse_identifier(line_number, target)?),
+        ["jz", ..] => {
+            let (condition, target) = parse_conditional_jump(line_number, &line[2..])?;
+            Instruction::JumpIfZero { target, condition }
+        }
+        ["jnz", ..] => {
+            let (condition, target) = parse_conditional_jump(line_number, &line[3..])?;
+            Instruction::JumpIfNotZero { target, condition }
+        }
+        ["jumptable", ..] => parse_jump_table(line_number, &line["jumptable".len()..])?,
+        [dst, "=", src] => Instruction::Copy {
+            src: parse_value(line_number, src)?,
+            dst: parse_destination(line_number, dst)?,
+        },
+        [dst, "=", unary_operator, src] => Instruction::UnaryOperator {
+            unary_operator: parse_unary_operator(line_number, unary_operator)?,
+            src: parse_value(line_number, src)?,
+            dst: parse_destination(line_number, dst)?,
+        },
+        [dst, "=", src1, binary_operator, src2] => Instruction::BinaryOperator {
+            binary_operator: parse_binary_operator(line_number, binary_operator)?,
+            src1: parse_value(line_number, src1)?,
+            src2: parse_value(line_number, src2)?,
+            dst: parse_destination(line_number, dst)?,
+        },
+        _ => return Err(error(line_number, "unrecognised instruction")),
+    })
+}
+
+// `<condition>, <target>`
+fn parse_conditional_jump(line_number: usize, operands: &str) -> Result<(Value, String)> {
+    let Some((condition, target)) = operands.split_once(',') else {
+        return Err(error(line_number, "expected `<condition>, <target>`"));
+    };
+    Ok((
+        parse_value(line_number, condition.trim())?,
+        parse_identifier(line_number, target.trim())?,
+    ))
+}
+
+// `<index>, <target> <target> ..., <default>`
+fn parse_jump_table(line_number: usize, operands: &str) -> Result<Instruction> {
+    let [index, targets, default] = operands.split(',').collect::<Vec<_>>()[..] else {
+        return Err(error(
+            line_number,
+            "expected `<index>, <targets>, <default>`",
+        ));
+    };
+    Ok(Instruction::JumpTable {
+        index: parse_value(line_number, index.trim())?,
+        targets: targets
+            .split_whitespace()
+            .map(|target| parse_identifier(line_number, target))
+            .collect::<Result<_>>()?,
+        default: parse_identifier(line_number, default.trim())?,
+    })
+}
+
+fn parse_value(line_number: usize, token: &str) -> Result<Value> {
+    match token.parse::<i32>() {
+        Ok(val) => Ok(Value::Constant(val)),
+        Err(_) => Ok(Value::Var(parse_identifier(line_number, token)?)),
+    }
+}
+
+fn parse_destination(line_number: usize, token: &str) -> Result<Value> {
+    match parse_value(line_number, token)? {
+        Value::Constant(_) => Err(error(line_number, "cannot assign to a constant")),
+        var => Ok(var),
+    }
+}
+
+//generated names look like `tmp.x.3`, `main-tmp.4` or `break_main_tmp_label.5`
+fn parse_identifier(line_number: usize, token: &str) -> Result<String> {
+    let valid = token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if valid {
+        Ok(token.to_string())
+    } else {
+        Err(Error::TackyParseError {
+            line: line_number,
+            message: format!("invalid identifier `{}`", token),
+        })
+    }
+}
+
+fn parse_unary_operator(line_number: usize, token: &str) -> Result<UnaryOperator> {
+    Ok(match token {
+        "~" => UnaryOperator::Complement,
+        "-" => UnaryOperator::Negate,
+        "!" => UnaryOperator::Not,
+        "++" => UnaryOperator::Increment,
+        "--" => UnaryOperator::Decrement,
+        _ => return Err(error(line_number, "unrecognised unary operator")),
+    })
+}
+
+fn parse_binary_operator(line_number: usize, token: &str) -> Result<BinaryOperator> {
+    Ok(match token {
+        "+" => BinaryOperator::Add,
+        "-" => BinaryOperator::Subtract,
+        "*" => BinaryOperator::Multiply,
+        "/" => BinaryOperator::Divide,
+        "%" => BinaryOperator::Remainder,
+        "&" => BinaryOperator::BitwiseAnd,
+        "|" => BinaryOperator::BitwiseOr,
+        "^" => BinaryOperator::BitwiseXor,
+        "<<" => BinaryOperator::LeftShift,
+        ">>" => BinaryOperator::RightShift,
+        "==" => BinaryOperator::Equal,
+        "!=" => BinaryOperator::NotEqual,
+        "<" => BinaryOperator::LessThan,
+        "<=" => BinaryOperator::Leq,
+        ">" => BinaryOperator::GreaterThan,
+        ">=" => BinaryOperator::Geq,
+        _ => return Err(error(line_number, "unrecognised binary operator")),
+    })
+}
+
+//later passes make fresh temporaries and labels off TACKY_COUNTER, so start it past every number
+//already used as a suffix in the file to keep those from colliding with hand written names
+fn bump_counter(program: &Program) {
+    let Program::Program(FunctionDefinition::Function { body, .. }) = program;
+    let suffix = |name: &str| {
+        name.rsplit(['.', '_'])
+            .next()
+            .and_then(|suffix| suffix.parse::<i64>().ok())
+            .unwrap_or(0)
+    };
+    let max = body
+        .iter()
+        .flat_map(|instruction| match instruction {
+            Instruction::Return(value) => vec![value],
+            Instruction::UnaryOperator { src, dst, .. } | Instruction::Copy { src, dst } => {
+                vec![src, dst]
+            }
+            Instruction::BinaryOperator {
+                src1, src2, dst, ..
+            } => vec![src1, src2, dst],
+            Instruction::JumpIfZero { condition, .. }
+            | Instruction::JumpIfNotZero { condition, .. }
+            | Instruction::JumpTable {
+                index: condition, ..
+            } => vec![condition],
+            Instruction::Jump(_) | Instruction::Label(_) => vec![],
+        })
+        .filter_map(|value| match value {
+            Value::Var(name) => Some(suffix(name)),
+            Value::Constant(_) => None,
+        })
+        .chain(body.iter().flat_map(|instruction| {
+            match instruction {
+                Instruction::Jump(target)
+                | Instruction::Label(target)
+                | Instruction::JumpIfZero { target, .. }
+                | Instruction::JumpIfNotZero { target, .. } => vec![suffix(target)],
+                Instruction::JumpTable {
+                    targets, default, ..
+                } => targets
+                    .iter()
+                    .chain([default])
+                    .map(|target| suffix(target))
+                    .collect(),
+                _ => vec![],
+            }
+        }))
+        .max()
+        .unwrap_or(0);
+    TACKY_COUNTER.set(TACKY_COUNTER.get().max(max + 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::sink;
+
+    use super::parse_tacky;
+    use crate::compile::lexer::Lex;
+    use crate::compile::optimization::optimize;
+    use crate::compile::parser::parser;
+    use crate::compile::semantic_analysis::semantic_analysis;
+    use crate::compile::tacky::{Program, TACKY_COUNTER, tacky_gen};
+    use crate::compile::{
+        CompileOptions, OptLevel, Optimizations, compile_tacky, run_native, run_tacky,
+    };
+
+    const SOURCES: [&str; 3] = [
+        "int main(void) {
+            int n = 27;
+            int steps = 0;
+            while (n != 1) {
+                if (n % 2) n = 3 * n + 1; else n = n / 2;
+                steps++;
+                if (steps > 1000) break;
+            }
+            return steps + (n << 3) - -steps % 7;
+        }",
+        "int main(void) {
+            int total = 0;
+            for (int i = 0; i < 20; i = i + 1) {
+                switch (i % 6) {
+                    case 0: total += 1;
+                    case 1: total += 10; break;
+                    case 4: continue;
+                    default: total -= i;
+                }
+            }
+            return total < 0 ? -total : total && !(total == 3);
+        }",
+        "int main(void) {
+            int a = 5;
+            int b = ~a ^ 12;
+            if (a > 3 || b <= 0) goto done;
+            a = a * 100;
+        done:
+            return a + b;
+        }",
+    ];
+
+    fn optimizations(level: OptLevel) -> Optimizations {
+        Optimizations {
+            passes: level.passes(),
+            print_after: vec![],
+        }
+    }
+
+    fn tacky_of(source: &str, level: OptLevel) -> Program {
+        TACKY_COUNTER.set(0);
+        let ast =
+            semantic_analysis(parser(source.to_string().lex()).expect("parses")).expect("is valid");
+        let tacky = tacky_gen(ast).expect("lowers to tacky");
+        optimize(tacky, &optimizations(level), &mut sink()).expect("optimizes")
+    }
+
+    #[test]
+    fn print_parse_print_round_trips() {
+        for source in SOURCES {
+            for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+                let program = tacky_of(source, level);
+                let printed = program.to_string();
+                let parsed = parse_tacky(&printed).expect("printed tacky parses");
+                assert_eq!(
+                    format!("{:?}", parsed),
+                    format!("{:?}", program),
+                    "{}",
+                    printed
+                );
+                assert_eq!(parsed.to_string(), printed);
+            }
+        }
+    }
+
+    const SUM_LOOP: &str = include_str!("../../../tests/tacky/sum_loop.tacky");
+
+    #[test]
+    fn hand_written_tacky_runs_at_every_level() {
+        for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let result = run_tacky(
+                SUM_LOOP.to_string(),
+                true,
+                &optimizations(level),
+                &mut sink(),
+            );
+            assert_eq!(result.expect("runs"), 48, "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn hand_written_tacky_compiles_to_what_it_interprets_to() {
+        for level in [OptLevel::O0, OptLevel::O2] {
+            let options = CompileOptions {
+                optimizations: optimizations(level),
+                ..Default::default()
+            };
+            let mut asm = Vec::new();
+            compile_tacky(&mut asm, &mut sink(), SUM_LOOP.to_string(), &options).expect("compiles");
+            let name = format!("sum_loop_{:?}", level);
+            assert_eq!(run_native(&asm, &name), 48, "{:?}", level);
+        }
+    }
+}
@@ -4,6 +4,7 @@ use thiserror::Error;
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error, Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Error {
     #[error("Lexer error failed: char {char}")]
     LexerError { char: char },
@@ -11,9 +12,16 @@ pub(crate) enum Error {
     #[error("Parser error: expected {expected}, found {found}")]
     ParserError { expected: String, found: String },
 
+    #[error("TACKY parse error on line {line}: {message}")]
+    TackyParseError { line: usize, message: String },
+
     #[error("Semantic error: {0}")]
     SemanticError(&'static str),
 
+    #[error("TACKY interpreter error: {0}")]
+    InterpreterError(String),
+
+    #[allow(dead_code)]
     #[error("Assmebly generation failed: {0}")]
     AsmGenError(&'static str),
 
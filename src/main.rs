@@ -2,17 +2,23 @@ mod compile;
 mod error;
 use std::{
     fs::{File, read_to_string, remove_file},
-    io::{BufWriter, Write, stderr},
+    io::{BufWriter, Write, sink, stderr, stdout},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     process::{Command, exit},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use crate::compile::compile;
+use crate::compile::{
+    CompileOptions, Format, OptLevel, Optimizations, Pass, Stage, compile, compile_tacky, run_tacky,
+};
 
 #[derive(Parser, Debug)]
 struct Cli {
+    #[arg(required = true)]
     files: Vec<String>,
 
     #[arg(long)]
@@ -35,102 +41,329 @@ struct Cli {
 
     #[arg(long)]
     validate: bool,
+
+    /// stages to dump instead of compiling, e.g. `--emit=tokens,ast,tacky`
+    #[arg(long, value_delimiter = ',')]
+    emit: Vec<Stage>,
+
+    /// how `--emit` dumps are written. json writes one object per input file on its own line
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// optimization level, glued on like `-O2`. -O1 and up run every tacky optimization, and a bare
+    /// -O means -O1
+    #[arg(short = 'O', value_enum, default_value = "0")]
+    opt_level: OptLevel,
+
+    /// evaluate operations on constants at compile time
+    #[arg(long)]
+    fold_constants: bool,
+
+    /// remove code that can never run, and jumps and labels that make no difference
+    #[arg(long)]
+    eliminate_unreachable_code: bool,
+
+    /// replace reads of copied variables with the values copied into them
+    #[arg(long)]
+    propagate_copies: bool,
+
+    /// remove writes to variables that are never read afterwards
+    #[arg(long)]
+    eliminate_dead_stores: bool,
+
+    /// propagate constants along the paths the program can actually take, on ssa form
+    #[arg(long)]
+    sccp: bool,
+
+    /// reuse the result of an earlier computation of the same expression, on ssa form
+    #[arg(long)]
+    gvn: bool,
+
+    /// move computations that give the same result on every iteration out of loops, on ssa form
+    #[arg(long)]
+    licm: bool,
+
+    /// code generation options, e.g. `-fomit-frame-pointer`. a later one overrides an earlier one
+    #[arg(short = 'f', value_enum)]
+    codegen_options: Vec<CodegenOption>,
+
+    /// dump the tacky after each run of these optimization passes, which have to be enabled
+    #[arg(long, value_delimiter = ',')]
+    print_after: Vec<Pass>,
+
+    /// interpret the program's tacky and exit with what main returns instead of compiling it
+    #[arg(long, conflicts_with_all = ["lex", "parse", "validate", "tacky", "codegen", "emit", "s", "c"])]
+    run_tacky: bool,
+
+    /// number of translation units to preprocess, compile and assemble at once
+    #[arg(short = 'j', long, default_value = "1")]
+    jobs: NonZeroUsize,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CodegenOption {
+    /// address the stack off rsp and skip setting up rbp, on by default from -O1
+    OmitFramePointer,
+    NoOmitFramePointer,
+}
+
+/// what a single translation unit produced. stage dumps and diagnostics are held here instead of
+/// being printed by the worker so they come out in command line order no matter which unit finishes
+/// first
+struct Unit {
+    out: Vec<u8>,
+    /// the `.s` and `.o` files it wrote, which come out again if any unit fails
+    written: Vec<PathBuf>,
+    result: std::result::Result<(), String>,
 }
 
 //driver
 fn main() {
-    let cli = Cli::parse();
-    let paths = cli.files.into_iter().map(PathBuf::from).collect::<Vec<_>>();
-
-    for path in &paths {
-        //use preprocesser, emit intermediate file
-        let preprocess = Command::new("gcc")
-            .arg("-E")
-            .arg("-P")
-            .arg(&path)
-            .arg("-o")
-            .arg(path.with_extension("i"))
-            .output()
-            .expect("failed to execute preprocessor");
-        if !preprocess.status.success() {
+    //clap would take whatever follows a bare -O as its level
+    let cli = Cli::parse_from(std::env::args().map(|arg| match arg.as_str() {
+        "-O" => "-O1".to_string(),
+        _ => arg,
+    }));
+    let paths = cli.files.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let passes = cli
+        .opt_level
+        .passes()
+        .into_iter()
+        .chain(
+            [
+                (cli.fold_constants, Pass::FoldConstants),
+                (
+                    cli.eliminate_unreachable_code,
+                    Pass::EliminateUnreachableCode,
+                ),
+                (cli.propagate_copies, Pass::PropagateCopies),
+                (cli.eliminate_dead_stores, Pass::EliminateDeadStores),
+                (cli.sccp, Pass::Sccp),
+                (cli.gvn, Pass::Gvn),
+                (cli.licm, Pass::Licm),
+            ]
+            .into_iter()
+            .filter_map(|(enabled, pass)| enabled.then_some(pass)),
+        )
+        .collect::<Vec<_>>();
+    //a pass that never runs would never be printed
+    if let Some(pass) = cli.print_after.iter().find(|pass| !passes.contains(pass)) {
+        let name = pass.to_possible_value().expect("no pass is skipped");
+        eprintln!(
+            "--print-after={} needs that pass enabled, by its own flag or an -O level",
+            name.get_name()
+        );
+        exit(1);
+    }
+    let options = CompileOptions {
+        lex: cli.lex,
+        parse: cli.parse,
+        validate: cli.validate,
+        tacky: cli.tacky,
+        codegen: cli.codegen,
+        emit: cli.emit.clone(),
+        format: cli.format,
+        optimizations: Optimizations {
+            passes,
+            print_after: cli.print_after.clone(),
+        },
+        omit_frame_pointer: cli
+            .codegen_options
+            .last()
+            .map_or(cli.opt_level >= OptLevel::O1, |option| {
+                matches!(option, CodegenOption::OmitFramePointer)
+            }),
+    };
+    let assemble = !cli.s && !options.stops_early();
+
+    if cli.run_tacky {
+        let [path] = &paths[..] else {
+            eprintln!("--run-tacky takes a single file");
             exit(1);
+        };
+        match read_unit(path).and_then(|(code, from_tacky)| {
+            run_tacky(code, from_tacky, &options.optimizations, &mut stdout())
+                .map_err(|e| e.to_string())
+        }) {
+            Ok(code) => exit(code),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
+    }
 
-        //actually call compiler
-        let code = read_to_string(path.with_extension("i"))
-            .expect("failed to read intermediate post processing file");
+    let units = run_jobs(&paths, cli.jobs.get(), |path| {
+        let (mut out, mut written) = (Vec::new(), Vec::new());
+        let result = build_unit(path, &options, assemble, &mut out, &mut written);
+        Unit {
+            out,
+            written,
+            result,
+        }
+    });
 
-        //we can remove immedaitely since its in memory
-        let _ = remove_file(path.with_extension("i"));
+    let mut failed = false;
+    for unit in &units {
+        let _ = stdout().write_all(&unit.out);
+        if let Err(e) = &unit.result {
+            eprintln!("{}", e);
+            failed = true;
+        }
+    }
+    let _ = stdout().flush();
 
-        let mut asm_file_writer = BufWriter::new(
-            File::create(path.with_extension("s")).expect("failed to create asm file"),
-        );
-        match compile(
-            &mut asm_file_writer,
-            code,
-            cli.lex,
-            cli.parse,
-            cli.codegen,
-            cli.tacky,
-            cli.validate,
-        ) {
-            Ok(()) => (),
-            Err(e) => {
-                for path in paths {
-                    let _ = remove_file(path.with_extension("s"));
-                }
-                eprintln!("{}", e.to_string());
-                exit(1);
-            }
+    if failed {
+        //only what this run wrote, not whatever an earlier build left behind
+        for written in units.iter().flat_map(|unit| &unit.written) {
+            let _ = remove_file(written);
         }
+        exit(1);
     }
 
-    //assembler and linker
-    if !cli.s && !(cli.lex || cli.parse || cli.codegen || cli.tacky || cli.validate) {
-        let mut assemble = Command::new("gcc");
+    //linker
+    if assemble && !cli.c {
+        let mut link = Command::new("gcc");
         for path in &paths {
-            assemble.arg(path.with_extension("s"));
+            link.arg(path.with_extension("o"));
         }
 
-        let x = assemble
+        let x = link
             .arg("-o")
             .arg(paths[0].with_extension(""))
             .output()
-            .expect("failed to execute assembler and linker");
-        for path in paths {
-            let _ = remove_file(path.with_extension("s"));
+            .expect("failed to execute linker");
+        for path in &paths {
+            let _ = remove_file(path.with_extension("o"));
         }
         if !x.status.success() {
             stderr()
                 .write_all(&x.stderr)
-                .expect("failed to write assembler stage error to stderr");
+                .expect("failed to write linker stage error to stderr");
             exit(1);
         }
-    } else if !cli.s {
-        for path in paths {
-            let _ = remove_file(path.with_extension("s"));
-        }
-    } else if cli.c {
-        let mut assemble = Command::new("gcc");
-        assemble.arg("-c");
-        for path in &paths {
-            assemble.arg(path.with_extension("s"));
-        }
+    }
+}
+
+/// the parser and the passes over the ast recurse, so workers get as much stack as the main thread
+/// usually does instead of the 2 MiB default
+const WORKER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// runs `job` over every item on up to `jobs` threads, returning the results in the same order as
+/// `items`
+fn run_jobs<T: Sync, R: Send>(items: &[T], jobs: usize, job: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    //no point paying for a thread that would just be waited on
+    if jobs.min(items.len()) <= 1 {
+        return items.iter().map(job).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..jobs.min(items.len()))
+            .map(|_| {
+                thread::Builder::new()
+                    .stack_size(WORKER_STACK_SIZE)
+                    .spawn_scoped(scope, || {
+                        let mut done = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(item) = items.get(i) else {
+                                break done;
+                            };
+                            done.push((i, job(item)));
+                        }
+                    })
+                    .expect("failed to spawn compiler thread")
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("compiler thread panicked"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
 
-        let x = assemble
+/// preprocesses and compiles one file, then assembles it into an object file if `assemble` is set.
+/// `.tacky` files are already past the front end so they skip straight to the backend. every file
+/// it creates goes in `written`
+fn build_unit(
+    path: &Path,
+    options: &CompileOptions,
+    assemble: bool,
+    out: &mut Vec<u8>,
+    written: &mut Vec<PathBuf>,
+) -> std::result::Result<(), String> {
+    let (code, from_tacky) = read_unit(path)?;
+
+    let mut writer: Box<dyn Write> = if options.stops_early() {
+        Box::new(sink())
+    } else {
+        written.push(path.with_extension("s"));
+        Box::new(BufWriter::new(
+            File::create(path.with_extension("s")).expect("failed to create asm file"),
+        ))
+    };
+    if from_tacky {
+        compile_tacky(&mut writer, out, code, options)
+    } else {
+        compile(&mut writer, out, code, options)
+    }
+    .map_err(|e| e.to_string())?;
+    drop(writer);
+
+    //assembler
+    if assemble {
+        written.push(path.with_extension("o"));
+        let x = Command::new("gcc")
+            .arg("-c")
+            .arg(path.with_extension("s"))
             .arg("-o")
-            .arg(paths[0].with_extension(""))
+            .arg(path.with_extension("o"))
             .output()
-            .expect("failed to execute assembler and linker");
-        for path in paths {
-            let _ = remove_file(path.with_extension("s"));
-        }
+            .expect("failed to execute assembler");
+        let _ = remove_file(path.with_extension("s"));
         if !x.status.success() {
-            stderr()
-                .write_all(&x.stderr)
-                .expect("failed to write assembler stage error to stderr");
-            exit(1);
+            return Err(String::from_utf8_lossy(&x.stderr).trim_end().to_string());
         }
     }
+    Ok(())
+}
+
+/// the source to compile and whether it's tacky rather than c
+fn read_unit(path: &Path) -> std::result::Result<(String, bool), String> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "tacky")
+    {
+        let code = read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok((code, true))
+    } else {
+        Ok((preprocess(path)?, false))
+    }
+}
+
+fn preprocess(path: &Path) -> std::result::Result<String, String> {
+    //use preprocesser, emit intermediate file
+    let preprocess = Command::new("gcc")
+        .arg("-E")
+        .arg("-P")
+        .arg(path)
+        .arg("-o")
+        .arg(path.with_extension("i"))
+        .output()
+        .expect("failed to execute preprocessor");
+    if !preprocess.status.success() {
+        return Err(String::from_utf8_lossy(&preprocess.stderr)
+            .trim_end()
+            .to_string());
+    }
+
+    //read back what the preprocessor produced
+    let code = read_to_string(path.with_extension("i"))
+        .expect("failed to read intermediate post processing file");
+
+    //we can remove immedaitely since its in memory
+    let _ = remove_file(path.with_extension("i"));
+    Ok(code)
 }